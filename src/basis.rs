@@ -1,48 +1,457 @@
-use std::borrow::Cow;
-
+use num_bigint::BigInt;
 use num_rational::BigRational;
-use num_traits::Zero;
+use num_traits::{One, Signed, Zero};
 
-use crate::format::format_from_coeffs;
-use crate::pascal::{factorial, pick};
-use crate::polynomial::{x, Polynomial};
+use crate::format::{format_factored, format_from_coeffs, CoeffStyle, Formatter};
+use crate::interrupt;
+use crate::multipoint::eval_integers;
+use crate::parse::Parsed;
+use crate::pascal::factorial;
+use crate::polynomial::{content_and_primitive_coeffs, Polynomial};
+use crate::ring::{Coefficient, Fp};
 
 // --- Basis Enum ---
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub(crate) enum Basis {
     Standard,
     Binomial,
 }
 
 impl Polynomial {
+    /// The coefficients `b_k` with `self = sum(b_k * C(x, k))`. These are the
+    /// forward differences at 0, `b_k = Δ^k p(0)`, so they come from the values at
+    /// `0, 1, ..., n` by a difference table, which only subtracts.
     fn to_binomial_coeffs(&self) -> Vec<BigRational> {
-        let mut residual = self.clone();
-        let degree = self.degree();
-        let mut binomial_coeffs = vec![BigRational::zero(); degree + 1];
-
-        for n in (0..=degree).rev() {
-            let c_n = residual.coeff_at(n);
-            if !c_n.is_zero() {
-                binomial_coeffs[n] = &c_n * factorial(n);
-                residual -= pick(&x(), n) * &c_n;
+        let numers = self.numers();
+        if numers.is_empty() {
+            return vec![BigRational::zero()];
+        }
+        let n = numers.len() - 1;
+        let points: Vec<BigInt> = (0..=n).map(BigInt::from).collect();
+        let mut diffs = eval_integers(&numers, &points);
+        // After pass k, diffs[i] holds Δ^k p(i - k) for i >= k.
+        for k in 1..=n {
+            if interrupt::checkpoint() {
+                break;
+            }
+            for i in (k..=n).rev() {
+                let (low, high) = diffs.split_at_mut(i);
+                high[0] -= &low[i - 1];
             }
         }
-        binomial_coeffs
+        (diffs.into_iter())
+            .map(|d| BigRational::new(d, self.denom().clone()))
+            .collect()
+    }
+
+    /// The inverse of `to_binomial_coeffs`, for `B(b_0, ..., b_n)`. Over a common denominator `d`, the sum
+    /// nests as `(r_0 + x * (r_1 + (x - 1) * (r_2 + ...))) / (d * n!)` with
+    /// `r_k = d * b_k * n!/k!`, which needs integer arithmetic only.
+    pub(crate) fn from_binomial_coeffs(coeffs: &[BigRational]) -> Polynomial {
+        let (numers, denom) = BigRational::over_common_denominator(coeffs);
+        let n = coeffs.len().saturating_sub(1);
+        let mut scale = BigInt::one();
+        let mut result: Vec<BigInt> = Vec::with_capacity(coeffs.len());
+        for (k, b) in numers.into_iter().enumerate().rev() {
+            if interrupt::checkpoint() {
+                break;
+            }
+            // Multiply by x - k
+            if !result.is_empty() {
+                result.push(BigInt::zero());
+                for i in (1..result.len()).rev() {
+                    result[i] = &result[i - 1] - &result[i] * k;
+                }
+                result[0] *= -BigInt::from(k);
+            }
+            match result.first_mut() {
+                Some(constant) => *constant += b * &scale,
+                None => result.push(b * &scale),
+            }
+            scale *= k;
+        }
+        Polynomial::from_parts(result, denom * factorial(n), ())
+    }
+
+    pub(crate) fn integrality(&self) -> Integrality {
+        Integrality::new(self.to_binomial_coeffs())
     }
 }
 
-impl Basis {
-    pub(crate) fn format(&self, poly: &Polynomial) -> String {
-        let coeffs = match self {
-            Basis::Standard => Cow::Borrowed(poly.coeffs()),
-            Basis::Binomial => Cow::Owned(poly.to_binomial_coeffs()),
-        };
-        let format_term = |degree: usize| -> String {
-            match self {
-                Basis::Standard => format!("x^{}", degree),
-                Basis::Binomial => format!("C(x,{})", degree),
+// --- Integer-Valued Polynomials ---
+/// The highest degree whose integrality the page notes.
+const MAX_CERTIFIED_DEGREE: usize = 200;
+
+/// How far a polynomial is from taking integer values on the integers.
+///
+/// A polynomial is integer-valued exactly when its binomial-basis coefficients are
+/// integers, so those coefficients are the certificate either way.
+#[derive(Debug, Clone)]
+pub(crate) struct Integrality {
+    pub(crate) certificate: Vec<BigRational>,
+    /// The content of `certificate`, made positive.
+    content: BigRational,
+}
+
+impl Integrality {
+    /// From the binomial-basis coefficients of a polynomial.
+    pub(crate) fn new(certificate: Vec<BigRational>) -> Self {
+        let (content, _) = content_and_primitive_coeffs(&certificate);
+        Integrality {
+            certificate,
+            content: content.abs(),
+        }
+    }
+
+    /// What the page notes under a result: nonconstant polynomials over the
+    /// rationals up to `MAX_CERTIFIED_DEGREE`. Beyond that, or for sparse ones, the
+    /// certificate is too long to cite and takes quadratic time to compute.
+    pub(crate) fn of(value: &Parsed) -> Option<Integrality> {
+        match value {
+            Parsed::Rational(poly)
+                if (1..=MAX_CERTIFIED_DEGREE).contains(&poly.degree()) && !poly.is_sparse() =>
+            {
+                Some(poly.integrality())
             }
+            _ => None,
+        }
+    }
+
+    /// Whether the polynomial takes an integer value at every integer.
+    pub(crate) fn is_integer_valued(&self) -> bool {
+        self.content.is_integer()
+    }
+
+    /// The largest d such that the polynomial divided by d is still integer-valued,
+    /// i.e. the gcd of all its values at integers.
+    pub(crate) fn fixed_divisor(&self) -> &BigInt {
+        self.content.numer()
+    }
+
+    /// The smallest d > 0 such that the polynomial times d is integer-valued.
+    pub(crate) fn least_multiplier(&self) -> &BigInt {
+        self.content.denom()
+    }
+
+    /// An integer k at which the polynomial isn't an integer, and the value there.
+    ///
+    /// With `b_k` the first non-integer binomial coefficient, `p(k)` is `b_k` plus
+    /// integer multiples of the earlier ones.
+    pub(crate) fn witness(&self) -> Option<(usize, BigRational)> {
+        let k = self.certificate.iter().position(|c| !c.is_integer())?;
+        // p(k) = sum(b_j * C(k, j))
+        let mut binomial = BigInt::one();
+        let mut value = BigRational::zero();
+        for (j, b) in self.certificate[..=k].iter().enumerate() {
+            value += b * BigRational::from_integer(binomial.clone());
+            binomial = binomial * (k - j) / (j + 1);
+        }
+        Some((k, value))
+    }
+
+    /// The polynomial in the binomial basis, written out from the certificate.
+    pub(crate) fn format_certificate(
+        &self,
+        formatter: &dyn Formatter,
+        style: CoeffStyle,
+    ) -> String {
+        let terms = self.certificate.iter().enumerate();
+        let format_term = |degree: usize| Basis::Binomial.format_term(formatter, degree);
+        format_from_coeffs(terms, formatter, style, format_term)
+    }
+}
+
+impl Basis {
+    /// The value used for this basis in the basis selector and in permalinks.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Basis::Standard => "standard",
+            Basis::Binomial => "binomial",
+        }
+    }
+
+    pub(crate) fn by_name(name: &str) -> Option<Basis> {
+        match name {
+            "standard" => Some(Basis::Standard),
+            "binomial" => Some(Basis::Binomial),
+            _ => None,
+        }
+    }
+
+    fn format_term(&self, formatter: &dyn Formatter, degree: usize) -> String {
+        match self {
+            Basis::Standard => formatter.power(degree),
+            Basis::Binomial => formatter.binomial(degree),
+        }
+    }
+
+    /// Formats a polynomial over GF(`modulus`), with coefficients in `0..modulus`.
+    ///
+    /// `C(x, k)` has no meaning mod p once `k >= p`, so polynomials of degree `>= p`
+    /// are always shown in the standard basis, as are sparse ones (see `format`).
+    /// So is code for other systems, which would expand `C(x, k)` into fractions
+    /// that they can't reduce.
+    pub(crate) fn format_modular(
+        &self,
+        poly: &Polynomial<Fp>,
+        modulus: u64,
+        formatter: &dyn Formatter,
+    ) -> String {
+        let lifted = poly.clone().map(|c| c.lift(), ());
+        let standard_only =
+            poly.degree() as u64 >= modulus || poly.is_sparse() || formatter.emits_code();
+        let basis = if standard_only {
+            Basis::Standard
+        } else {
+            *self
         };
-        format_from_coeffs(&coeffs, format_term)
+        let terms = match basis {
+            Basis::Standard => lifted.terms(),
+            // The lift has integer coefficients, so its binomial coefficients are integers too.
+            Basis::Binomial => lifted
+                .to_binomial_coeffs()
+                .iter()
+                .map(|c| Fp::from_rational(c, modulus).unwrap().lift())
+                .enumerate()
+                .collect(),
+        };
+        if let Some(listing) = formatter.listing(&terms, basis, Some(modulus)) {
+            return listing;
+        }
+        let terms = terms.iter().map(|(k, c)| (*k, c));
+        let format_term = |degree: usize| basis.format_term(formatter, degree);
+        let poly_str = format_from_coeffs(terms, formatter, CoeffStyle::Fraction, format_term);
+        formatter.modular(&poly_str, modulus)
+    }
+
+    /// Sparse polynomials are always shown in the standard basis: in the binomial
+    /// basis they'd have a term for nearly every degree.
+    pub(crate) fn format(
+        &self,
+        poly: &Polynomial,
+        formatter: &dyn Formatter,
+        style: CoeffStyle,
+        factor_content: bool,
+    ) -> String {
+        let basis = if poly.is_sparse() {
+            Basis::Standard
+        } else {
+            *self
+        };
+        let format_term = |degree: usize| basis.format_term(formatter, degree);
+        if factor_content && !formatter.emits_listing() {
+            return match basis {
+                Basis::Standard => {
+                    let (content, primitive) = poly.content_and_primitive_part();
+                    let terms = primitive.terms();
+                    let terms = terms.iter().map(|(k, c)| (*k, c));
+                    format_factored(&content, terms, formatter, style, format_term)
+                }
+                Basis::Binomial => {
+                    let binomial_coeffs = poly.to_binomial_coeffs();
+                    let (content, primitive) = content_and_primitive_coeffs(&binomial_coeffs);
+                    let terms = primitive.iter().enumerate();
+                    format_factored(&content, terms, formatter, style, format_term)
+                }
+            };
+        }
+        let terms = match basis {
+            Basis::Standard => poly.terms(),
+            Basis::Binomial => poly.to_binomial_coeffs().into_iter().enumerate().collect(),
+        };
+        if let Some(listing) = formatter.listing(&terms, basis, None) {
+            return listing;
+        }
+        let terms = terms.iter().map(|(k, c)| (*k, c));
+        format_from_coeffs(terms, formatter, style, format_term)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+
+    use super::{Basis, Integrality, MAX_CERTIFIED_DEGREE};
+    use crate::format::formatter_by_name;
+    use crate::parse::{parse, Associativity, Bindings, Parsed};
+    use crate::polynomial::Polynomial;
+
+    #[test]
+    fn binomial_coeffs_round_trip() {
+        let budget = crate::budget::Budget::default();
+        for query in [
+            "0",
+            "7",
+            "x/2 + 1/3",
+            "C(x, 5) - 3*x^2",
+            "(x/3 - 1)^12",
+            "P(x, 20)/7",
+        ] {
+            let parsed = parse(query, &Bindings::default(), &budget, Associativity::Right);
+            let Ok(Parsed::Rational(poly)) = parsed else {
+                panic!("expected a polynomial for {}", query);
+            };
+            let coeffs = poly.to_binomial_coeffs();
+            assert_eq!(Polynomial::from_binomial_coeffs(&coeffs), poly, "{}", query);
+        }
+    }
+
+    #[test]
+    fn binomial_coeffs_are_forward_differences_at_zero() {
+        let budget = crate::budget::Budget::default();
+        let coeffs = |query: &str| {
+            let parsed = parse(query, &Bindings::default(), &budget, Associativity::Right);
+            let Ok(Parsed::Rational(poly)) = parsed else {
+                panic!("expected a polynomial for {}", query);
+            };
+            let coeffs: Vec<String> = (poly.to_binomial_coeffs().iter())
+                .map(ToString::to_string)
+                .collect();
+            coeffs.join(" ")
+        };
+        assert_eq!(coeffs("0"), "0");
+        assert_eq!(coeffs("x^2"), "0 1 2");
+        assert_eq!(coeffs("x^3/6 - 1/2"), "-1/2 1/6 1 1");
+        // Past the degree where evaluation splits the polynomial
+        let expected = format!("{}3", "0 ".repeat(70));
+        assert_eq!(coeffs("3*C(x, 70)"), expected);
+        assert_eq!(
+            coeffs("C(x + 1, 70) - C(x, 70)"),
+            format!("{}1", "0 ".repeat(69))
+        );
+    }
+
+    fn integrality(query: &str) -> Option<Integrality> {
+        let budget = crate::budget::Budget::default();
+        let parsed = parse(query, &Bindings::default(), &budget, Associativity::Right);
+        Integrality::of(&parsed.unwrap())
+    }
+
+    #[test]
+    fn integrality_certifies_divisors_and_witnesses() {
+        let half_square = integrality("x*(x - 1)/2").unwrap();
+        assert!(half_square.is_integer_valued());
+        assert_eq!(half_square.fixed_divisor(), &BigInt::from(1));
+        assert_eq!(half_square.least_multiplier(), &BigInt::from(1));
+        assert_eq!(half_square.witness(), None);
+
+        // Fermat: p divides x^p - x, and in fact so does every prime q with q - 1 | p - 1.
+        for (p, divisor) in [(2, 2), (3, 6), (5, 30), (7, 42), (13, 2730)] {
+            let fermat = integrality(&format!("x^{} - x", p)).unwrap();
+            assert!(fermat.is_integer_valued());
+            assert_eq!(fermat.fixed_divisor(), &BigInt::from(divisor), "p = {}", p);
+            assert_eq!(fermat.least_multiplier(), &BigInt::from(1));
+            assert_eq!(fermat.witness(), None);
+        }
+
+        let half = integrality("x/2").unwrap();
+        assert!(!half.is_integer_valued());
+        assert_eq!(half.least_multiplier(), &BigInt::from(2));
+        assert_eq!(
+            half.witness(),
+            Some((1, BigRational::new(1.into(), 2.into())))
+        );
+        let sixth = integrality("x^3/6 + 2").unwrap();
+        assert_eq!(sixth.least_multiplier(), &BigInt::from(6));
+        assert_eq!(
+            sixth.witness(),
+            Some((1, BigRational::new(13.into(), 6.into())))
+        );
+    }
+
+    #[test]
+    fn integrality_is_only_noted_up_to_a_degree() {
+        assert!(integrality("7").is_none());
+        assert!(integrality("mod 5: x^2").is_none());
+        assert!(integrality("x^1000000 + 1").is_none());
+        assert!(integrality(&format!("C(x, {})", MAX_CERTIFIED_DEGREE)).is_some());
+        assert!(integrality(&format!("C(x, {})", MAX_CERTIFIED_DEGREE + 1)).is_none());
+    }
+
+    #[test]
+    fn modular_code_is_valid_in_its_language() {
+        let budget = crate::budget::Budget::default();
+        let parsed = parse(
+            "mod 7: 3*C(x, 2) + 1",
+            &Bindings::default(),
+            &budget,
+            Associativity::Right,
+        );
+        let Ok(Parsed::Modular { poly, modulus }) = parsed else {
+            panic!("expected a result mod 7");
+        };
+        let format =
+            |name| Basis::Binomial.format_modular(&poly, modulus, formatter_by_name(name).unwrap());
+        assert_eq!(format("plain"), "3*C(x,2) + 1 (mod 7)");
+        assert_eq!(format("sympy"), "Poly(5*x**2 + 2*x + 1, x, modulus=7)");
+        assert_eq!(format("mathematica"), "PolynomialMod[5*x^2 + 2*x + 1, 7]");
+        assert_eq!(format("maxima"), "polymod(5*x^2 + 2*x + 1, 7)");
+    }
+
+    #[test]
+    fn rust_code_needs_only_the_num_crates() {
+        let budget = crate::budget::Budget::default();
+        let rust = formatter_by_name("rust").unwrap();
+        let parse = |query| parse(query, &Bindings::default(), &budget, Associativity::Right);
+        let Ok(Parsed::Rational(poly)) = parse("C(x, 3) - 1/2") else {
+            panic!("expected a polynomial");
+        };
+        let style = crate::format::CoeffStyle::Fraction;
+        assert_eq!(
+            Basis::Standard.format(&poly, rust, style, true),
+            r#"// p(x) = sum of c * x^k over TERMS (k, c), exactly
+use num_rational::BigRational;
+
+const TERMS: &[(i32, &str)] = &[(3, "1/6"), (2, "-1/2"), (1, "1/3"), (0, "-1/2")];
+
+fn p(x: &BigRational) -> BigRational {
+    TERMS
+        .iter()
+        .map(|&(k, c)| c.parse::<BigRational>().unwrap() * x.pow(k))
+        .sum()
+}
+"#
+        );
+        assert_eq!(
+            Basis::Binomial.format(&poly, rust, style, false),
+            r#"// p(x) = sum of c * C(x, k) over TERMS (k, c), exactly
+use num_rational::BigRational;
+
+const TERMS: &[(i32, &str)] = &[(3, "1"), (0, "-1/2")];
+
+fn p(x: &BigRational) -> BigRational {
+    let one = BigRational::from_integer(1.into());
+    TERMS
+        .iter()
+        .map(|&(k, c)| {
+            // C(x, k) = x/1 * (x - 1)/2 * ... * (x - k + 1)/k
+            let binomial = (0..k).fold(one.clone(), |b, i| {
+                b * (x - BigRational::from_integer(i.into())) / BigRational::from_integer((i + 1).into())
+            });
+            c.parse::<BigRational>().unwrap() * binomial
+        })
+        .sum()
+}
+"#
+        );
+        let Ok(Parsed::Modular { poly, modulus }) = parse("mod 7: 3*C(x, 2) + 1") else {
+            panic!("expected a result mod 7");
+        };
+        assert_eq!(
+            Basis::Binomial.format_modular(&poly, modulus, rust),
+            r#"// p(x) = sum of c * x^k mod MODULUS over TERMS (k, c)
+const MODULUS: u64 = 7;
+const TERMS: &[(u64, u64)] = &[(2, 5), (1, 2), (0, 1)];
+
+fn p(x: u64) -> u64 {
+    let x = x % MODULUS;
+    TERMS.iter().fold(0, |sum, &(k, c)| {
+        let power = (0..k).fold(1, |power, _| power * x % MODULUS);
+        (sum + c * power) % MODULUS
+    })
+}
+"#
+        );
     }
 }
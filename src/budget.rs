@@ -0,0 +1,234 @@
+use std::fmt;
+use std::str::FromStr;
+
+// --- Computation Budgets ---
+/// Upper bounds on the size of a computation, checked before the expensive step
+/// so that a runaway input fails fast instead of hanging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Budget {
+    /// Bounds the degree of a result, or its number of terms if it's stored sparsely.
+    pub(crate) max_degree: u64,
+    /// Bounds the numerator plus denominator bit length of any coefficient.
+    pub(crate) max_coeff_bits: u64,
+    pub(crate) max_factorial: u64,
+    /// How deeply parentheses, function arguments, unary minus, factorials and
+    /// right-associative powers may nest.
+    pub(crate) max_depth: u64,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget {
+            max_degree: 10_000,
+            max_coeff_bits: 1_000_000,
+            max_factorial: 20_000,
+            max_depth: 200,
+        }
+    }
+}
+
+impl fmt::Display for Budget {
+    /// The limits, separated by spaces, in the order they're declared.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.max_degree, self.max_coeff_bits, self.max_factorial, self.max_depth
+        )
+    }
+}
+
+impl FromStr for Budget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid budget '{}'", s);
+        let limits = s
+            .split(' ')
+            .map(|limit| limit.parse().map_err(|_| invalid()))
+            .collect::<Result<Vec<u64>, _>>()?;
+        let [max_degree, max_coeff_bits, max_factorial, max_depth] = limits[..] else {
+            return Err(invalid());
+        };
+        Ok(Budget {
+            max_degree,
+            max_coeff_bits,
+            max_factorial,
+            max_depth,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Limit {
+    Degree,
+    /// The number of terms of a sparse result, bounded by `max_degree`.
+    Terms,
+    CoeffBits,
+    Factorial,
+    Depth,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Limit::Degree => "degree",
+            Limit::Terms => "number of terms",
+            Limit::CoeffBits => "coefficient size in bits",
+            Limit::Factorial => "factorial argument",
+            Limit::Depth => "nesting depth",
+        })
+    }
+}
+
+/// The error for a computation that would go over its `Budget`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LimitExceeded {
+    pub(crate) limit: Limit,
+    /// What the computation would need; estimates for coefficient sizes.
+    pub(crate) requested: u64,
+    pub(crate) allowed: u64,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Limit exceeded: {} would reach {}, the limit is {}",
+            self.limit, self.requested, self.allowed
+        )
+    }
+}
+
+impl From<LimitExceeded> for String {
+    fn from(e: LimitExceeded) -> String {
+        e.to_string()
+    }
+}
+
+impl Budget {
+    pub(crate) fn check(&self, limit: Limit, requested: u64) -> Result<(), LimitExceeded> {
+        let allowed = match limit {
+            Limit::Degree | Limit::Terms => self.max_degree,
+            Limit::CoeffBits => self.max_coeff_bits,
+            Limit::Factorial => self.max_factorial,
+            Limit::Depth => self.max_depth,
+        };
+        if requested <= allowed {
+            Ok(())
+        } else {
+            Err(LimitExceeded {
+                limit,
+                requested,
+                allowed,
+            })
+        }
+    }
+}
+
+/// The number of bits needed to write `n`.
+pub(crate) fn bit_length(n: u64) -> u64 {
+    u64::from(u64::BITS - n.leading_zeros())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Budget, Limit, LimitExceeded};
+    use crate::parse::{parse, Associativity, Bindings};
+
+    fn eval(query: &str) -> Result<String, String> {
+        let parsed = parse(
+            query,
+            &Bindings::default(),
+            &Budget::default(),
+            Associativity::Right,
+        )?;
+        Ok(parsed.to_string())
+    }
+
+    fn exceeded(limit: Limit, requested: u64, allowed: u64) -> Result<String, String> {
+        Err(LimitExceeded {
+            limit,
+            requested,
+            allowed,
+        }
+        .into())
+    }
+
+    #[test]
+    fn runaway_inputs_stop_at_a_limit() {
+        let budget = Budget::default();
+        assert_eq!(
+            eval("x^100000000"),
+            exceeded(Limit::Degree, 100_000_000, budget.max_coeff_bits)
+        );
+        assert_eq!(
+            eval("(x + 1)^100000000"),
+            exceeded(Limit::Degree, 100_000_000, budget.max_degree)
+        );
+        assert_eq!(
+            eval("1000000!"),
+            exceeded(Limit::Factorial, 1_000_000, budget.max_factorial)
+        );
+        assert_eq!(
+            eval("(x + 1)^5000 * (x - 1)^5001"),
+            exceeded(Limit::Degree, 10_001, budget.max_degree)
+        );
+        // Sparse results count terms, and go as far as the coefficient bits.
+        assert!(eval("x^1000000 + 1").is_ok());
+        assert!(matches!(eval("(x^1000 + x + 1)^200"), Err(e) if e.contains("number of terms")));
+    }
+
+    #[test]
+    fn coefficients_stop_at_the_bit_limit() {
+        let err = eval("2^2000000").unwrap_err();
+        assert!(
+            err.starts_with("Limit exceeded: coefficient size in bits"),
+            "{}",
+            err
+        );
+        let err = eval("(2*x + 3)^1000000").unwrap_err();
+        assert!(err.contains("limit is"), "{}", err);
+        let err = eval("(12345678901234567890*x + 1)^60000 * 1").unwrap_err();
+        assert!(err.contains("limit is"), "{}", err);
+        let err = eval("(3^600000)*(3^600000)").unwrap_err();
+        assert!(
+            err.starts_with("Limit exceeded: coefficient size in bits"),
+            "{}",
+            err
+        );
+        assert!(eval("2^400000").is_ok());
+    }
+
+    #[test]
+    fn nesting_stops_at_the_depth_limit() {
+        let nested = |depth: usize| format!("{}x{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(eval(&nested(150)).is_ok());
+        let err = eval(&nested(201)).unwrap_err();
+        assert!(err.starts_with("Limit exceeded: nesting depth"), "{}", err);
+        // Every kind of nesting counts, and none of them overflows the stack.
+        for deep in [
+            "-".repeat(10_000) + "x",
+            "C(".repeat(10_000) + "x",
+            format!("x{}", "^2".repeat(10_000)),
+            nested(100_000),
+        ] {
+            let err = eval(&deep).unwrap_err();
+            assert!(err.starts_with("Limit exceeded: nesting depth"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn budgets_round_trip_through_strings() {
+        let budget = Budget {
+            max_degree: 1,
+            max_coeff_bits: 2,
+            max_factorial: 3,
+            max_depth: 4,
+        };
+        assert_eq!(budget.to_string(), "1 2 3 4");
+        assert_eq!("1 2 3 4".parse(), Ok(budget));
+        assert!("1 2 3".parse::<Budget>().is_err());
+        assert!("1 2 3 -4".parse::<Budget>().is_err());
+    }
+}
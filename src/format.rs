@@ -1,58 +1,381 @@
 use num_rational::BigRational;
 use num_traits::{One, Signed, Zero};
 
-pub(crate) fn format_from_coeffs(
-    coeffs: &[BigRational],
+mod coeff_style;
+mod formatter;
+
+pub(crate) use self::coeff_style::CoeffStyle;
+pub(crate) use self::formatter::{by_name as formatter_by_name, Formatter, Plain, Unicode};
+
+/// Formats coefficients paired with their exponents, lowest first: a dense list as
+/// `coeffs.iter().enumerate()`, or just the nonzero terms of a sparse polynomial.
+pub(crate) fn format_from_coeffs<'a>(
+    coeffs: impl DoubleEndedIterator<Item = (usize, &'a BigRational)>,
+    formatter: &dyn Formatter,
+    style: CoeffStyle,
     format_term: impl Fn(usize) -> String,
 ) -> String {
-    if coeffs.is_empty() {
-        return "0".to_string();
-    }
-
     let mut terms = Vec::new();
-    for (i, coeff) in coeffs.iter().enumerate().rev() {
+    for (i, coeff) in coeffs.rev() {
         if coeff.is_zero() {
             continue;
         }
 
         let sign = if terms.is_empty() {
             if coeff.is_negative() {
-                "-"
+                formatter.minus().to_string()
             } else {
-                ""
+                "".to_string()
             }
         } else if coeff.is_negative() {
-            " - "
+            format!(" {} ", formatter.minus())
         } else {
-            " + "
+            " + ".to_string()
         };
         let abs_coeff = coeff.abs();
-        let coeff_str = if abs_coeff.is_one() {
-            "".to_string()
-        } else if abs_coeff.is_integer() {
-            format!("{}*", abs_coeff)
-        } else {
-            format!("({})*", abs_coeff)
-        };
-
-        let var_str = match i {
-            0 => format!("{}", abs_coeff), // Constant term
-            1 => "x".to_string(),
-            _ => format_term(i),
-        };
 
-        let term = if i == 0 || abs_coeff.is_one() {
-            format!("{}{}", sign, var_str)
-        } else {
-            format!("{}{}{}", sign, coeff_str, var_str)
+        let term = match i {
+            0 => formatter.constant(&abs_coeff, style), // Constant term
+            1 => formatter.scaled(&abs_coeff, style, &formatter.variable()),
+            _ => formatter.scaled(&abs_coeff, style, &format_term(i)),
         };
 
-        terms.push(term);
+        terms.push(format!("{}{}", sign, term));
     }
 
     if terms.is_empty() {
-        "0".to_string()
+        formatter.zero()
     } else {
         terms.join("")
     }
 }
+
+/// Formats `content * primitive`, pulling the content (common denominator and
+/// numerator gcd) out in front, e.g. `(x^3 + 3*x^2 + 2*x)/6`.
+pub(crate) fn format_factored<'a>(
+    content: &BigRational,
+    primitive: impl DoubleEndedIterator<Item = (usize, &'a BigRational)> + Clone,
+    formatter: &dyn Formatter,
+    style: CoeffStyle,
+    format_term: impl Fn(usize) -> String,
+) -> String {
+    let num_terms = primitive.clone().filter(|(_, c)| !c.is_zero()).count();
+    if num_terms < 2 || content.abs().is_one() {
+        let terms: Vec<(usize, BigRational)> = primitive.map(|(i, c)| (i, c * content)).collect();
+        let terms = terms.iter().map(|(i, c)| (*i, c));
+        return format_from_coeffs(terms, formatter, style, format_term);
+    }
+    let inner = format_from_coeffs(primitive, formatter, style, format_term);
+    let sign = if content.is_negative() {
+        formatter.minus()
+    } else {
+        ""
+    };
+    format!("{}{}", sign, formatter.factored(&content.abs(), &inner))
+}
+
+/// Formats a single (possibly negative) number, such as the result of an evaluation.
+pub(crate) fn format_number(
+    value: &BigRational,
+    formatter: &dyn Formatter,
+    style: CoeffStyle,
+) -> String {
+    format_from_coeffs(
+        std::iter::once((0, value)),
+        formatter,
+        style,
+        |_| unreachable!(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+
+    use super::coeff_style::MAX_DECIMAL_DIGITS;
+    use super::{formatter_by_name, CoeffStyle};
+    use crate::basis::Basis;
+    use crate::parse::{parse, Associativity, Bindings, Parsed};
+    use crate::polynomial::Polynomial;
+
+    /// Negative and fractional coefficients, zero, a negative constant, and a
+    /// polynomial that is short in the binomial basis.
+    const INPUTS: [&str; 4] = [
+        "-x^3/2 + 7/3*x - 1",
+        "0",
+        "-5/2",
+        "3*C(x, 2) - C(x, 3)/2 + 1",
+    ];
+
+    fn poly(query: &str) -> Polynomial {
+        let budget = crate::budget::Budget::default();
+        match parse(query, &Bindings::default(), &budget, Associativity::Right) {
+            Ok(Parsed::Rational(poly)) => poly,
+            other => panic!("expected a polynomial for {}, got {:?}", query, other),
+        }
+    }
+
+    /// Checks each of `INPUTS` in the standard and the binomial basis.
+    fn assert_renders(name: &str, expected: [(&str, &str); 4]) {
+        let formatter = formatter_by_name(name).unwrap();
+        for (query, (standard, binomial)) in INPUTS.into_iter().zip(expected) {
+            let poly = poly(query);
+            let format = |basis: Basis| basis.format(&poly, formatter, CoeffStyle::Fraction, false);
+            assert_eq!(format(Basis::Standard), standard, "{} in {}", query, name);
+            assert_eq!(format(Basis::Binomial), binomial, "{} in {}", query, name);
+        }
+    }
+
+    #[test]
+    fn latex() {
+        assert_renders(
+            "latex",
+            [
+                (
+                    r"-\frac{1}{2}x^{3} + \frac{7}{3}x - 1",
+                    r"-3\binom{x}{3} - 3\binom{x}{2} + \frac{11}{6}x - 1",
+                ),
+                ("0", "0"),
+                (r"-\frac{5}{2}", r"-\frac{5}{2}"),
+                (
+                    r"-\frac{1}{12}x^{3} + \frac{7}{4}x^{2} - \frac{5}{3}x + 1",
+                    r"-\frac{1}{2}\binom{x}{3} + 3\binom{x}{2} + 1",
+                ),
+            ],
+        );
+        let latex = formatter_by_name("latex").unwrap();
+        assert_eq!(
+            Basis::Standard.format(&poly("x^12 - 2*x"), latex, CoeffStyle::Fraction, false),
+            "x^{12} - 2x"
+        );
+    }
+
+    #[test]
+    fn plain_text_and_code() {
+        assert_renders(
+            "plain",
+            [
+                (
+                    "-(1/2)*x^3 + (7/3)*x - 1",
+                    "-3*C(x,3) - 3*C(x,2) + (11/6)*x - 1",
+                ),
+                ("0", "0"),
+                ("-5/2", "-5/2"),
+                (
+                    "-(1/12)*x^3 + (7/4)*x^2 - (5/3)*x + 1",
+                    "-(1/2)*C(x,3) + 3*C(x,2) + 1",
+                ),
+            ],
+        );
+        assert_renders(
+            "sympy",
+            [
+                (
+                    "-Rational(1,2)*x**3 + Rational(7,3)*x - 1",
+                    "-3*binomial(x,3) - 3*binomial(x,2) + Rational(11,6)*x - 1",
+                ),
+                ("0", "0"),
+                ("-Rational(5,2)", "-Rational(5,2)"),
+                (
+                    "-Rational(1,12)*x**3 + Rational(7,4)*x**2 - Rational(5,3)*x + 1",
+                    "-Rational(1,2)*binomial(x,3) + 3*binomial(x,2) + 1",
+                ),
+            ],
+        );
+        assert_renders(
+            "mathematica",
+            [
+                (
+                    "-(1/2)*x^3 + (7/3)*x - 1",
+                    "-3*Binomial[x,3] - 3*Binomial[x,2] + (11/6)*x - 1",
+                ),
+                ("0", "0"),
+                ("-5/2", "-5/2"),
+                (
+                    "-(1/12)*x^3 + (7/4)*x^2 - (5/3)*x + 1",
+                    "-(1/2)*Binomial[x,3] + 3*Binomial[x,2] + 1",
+                ),
+            ],
+        );
+        assert_renders(
+            "maxima",
+            [
+                (
+                    "-(1/2)*x^3 + (7/3)*x - 1",
+                    "-3*binomial(x,3) - 3*binomial(x,2) + (11/6)*x - 1",
+                ),
+                ("0", "0"),
+                ("-5/2", "-5/2"),
+                (
+                    "-(1/12)*x^3 + (7/4)*x^2 - (5/3)*x + 1",
+                    "-(1/2)*binomial(x,3) + 3*binomial(x,2) + 1",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn unicode() {
+        assert_renders(
+            "unicode",
+            [
+                ("−¹⁄₂x³ + ⁷⁄₃x − 1", "−3⁽ˣ₃⁾ − 3⁽ˣ₂⁾ + ¹¹⁄₆x − 1"),
+                ("0", "0"),
+                ("−⁵⁄₂", "−⁵⁄₂"),
+                ("−¹⁄₁₂x³ + ⁷⁄₄x² − ⁵⁄₃x + 1", "−¹⁄₂⁽ˣ₃⁾ + 3⁽ˣ₂⁾ + 1"),
+            ],
+        );
+        let unicode = formatter_by_name("unicode").unwrap();
+        let poly = poly("x^12 - 10*C(x, 10)");
+        assert!(Basis::Standard
+            .format(&poly, unicode, CoeffStyle::Fraction, false)
+            .starts_with("x¹² − ¹⁄₃₆₂₈₈₀x¹⁰ + "));
+        assert!(Basis::Binomial
+            .format(&poly, unicode, CoeffStyle::Fraction, false)
+            .starts_with("479001600⁽ˣ₁₂⁾ + 2634508800⁽ˣ₁₁⁾ + 6187103990⁽ˣ₁₀⁾ + "));
+    }
+
+    #[test]
+    fn coefficient_styles() {
+        let number = |name: &str, style: CoeffStyle, n: i64, d: i64| {
+            let value = BigRational::new(n.into(), d.into());
+            super::format_number(&value, formatter_by_name(name).unwrap(), style)
+        };
+        let plain = |style, n, d| number("plain", style, n, d);
+        assert_eq!(plain(CoeffStyle::Fraction, -7, 3), "-7/3");
+        assert_eq!(plain(CoeffStyle::Fraction, 6, 3), "2");
+
+        assert_eq!(plain(CoeffStyle::Mixed, -7, 3), "-2 1/3");
+        assert_eq!(plain(CoeffStyle::Mixed, 1, 3), "1/3");
+        assert_eq!(number("latex", CoeffStyle::Mixed, 7, 3), r"2\frac{1}{3}");
+        assert_eq!(number("unicode", CoeffStyle::Mixed, -7, 3), "−2¹⁄₃");
+
+        assert_eq!(plain(CoeffStyle::Decimal, 7, 3), "2.(3)");
+        assert_eq!(plain(CoeffStyle::Decimal, -1, 8), "-0.125");
+        assert_eq!(plain(CoeffStyle::Decimal, 1, 6), "0.1(6)");
+        assert_eq!(plain(CoeffStyle::Decimal, 1, 7), "0.(142857)");
+        assert_eq!(
+            number("latex", CoeffStyle::Decimal, 7, 3),
+            r"2.\overline{3}"
+        );
+        assert_eq!(number("unicode", CoeffStyle::Decimal, 1, 6), "0.1(6)");
+
+        assert_eq!(plain(CoeffStyle::Approximate(3), 7, 3), "2.333");
+        assert_eq!(plain(CoeffStyle::Approximate(3), -2, 3), "-0.667");
+        assert_eq!(plain(CoeffStyle::Approximate(2), 1, 8), "0.13");
+        assert_eq!(plain(CoeffStyle::Approximate(0), 5, 2), "3");
+        assert_eq!(plain(CoeffStyle::Approximate(4), 1, 1), "1");
+
+        // Code keeps exact values whatever the style.
+        for style in [
+            CoeffStyle::Mixed,
+            CoeffStyle::Decimal,
+            CoeffStyle::Approximate(2),
+        ] {
+            assert_eq!(number("sympy", style, 7, 3), "Rational(7,3)");
+            assert_eq!(number("mathematica", style, 7, 3), "7/3");
+            assert_eq!(number("maxima", style, -7, 3), "-7/3");
+        }
+
+        // The styles apply to every coefficient, parenthesized where needed.
+        let poly = poly("7/3*x^2 - x/8 + 1/6");
+        let plain = formatter_by_name("plain").unwrap();
+        let format = |style| Basis::Standard.format(&poly, plain, style, false);
+        assert_eq!(format(CoeffStyle::Mixed), "(2 1/3)*x^2 - (1/8)*x + 1/6");
+        assert_eq!(format(CoeffStyle::Decimal), "2.(3)*x^2 - 0.125*x + 0.1(6)");
+        assert_eq!(
+            format(CoeffStyle::Approximate(2)),
+            "2.33*x^2 - 0.13*x + 0.17"
+        );
+    }
+
+    #[test]
+    fn decimals_stop_at_the_digit_limit() {
+        let plain = formatter_by_name("plain").unwrap();
+        let decimal = |value: &BigRational| super::format_number(value, plain, CoeffStyle::Decimal);
+        let two = BigInt::from(2);
+        // 99 fixed digits and one repeating one make exactly MAX_DECIMAL_DIGITS ...
+        let at_limit = BigRational::new(1.into(), 3 * two.pow(99));
+        let shown = decimal(&at_limit);
+        assert!(shown.starts_with("0.") && shown.ends_with(")"), "{}", shown);
+        let digits = shown.chars().filter(char::is_ascii_digit).count();
+        assert_eq!(digits, 1 + MAX_DECIMAL_DIGITS);
+        // ... and one more falls back to the fraction.
+        let past_limit = BigRational::new(1.into(), 3 * two.pow(100));
+        assert_eq!(decimal(&past_limit), past_limit.to_string());
+        // Likewise for decimals that terminate, and for long periods: 1/97 repeats
+        // after 96 digits, 1/109 after 108.
+        assert!(!decimal(&BigRational::new(1.into(), two.pow(100))).contains('/'));
+        assert!(decimal(&BigRational::new(1.into(), two.pow(101))).contains('/'));
+        assert!(decimal(&BigRational::new(1.into(), 97.into())).starts_with("0.(010309"));
+        assert_eq!(decimal(&BigRational::new(1.into(), 109.into())), "1/109");
+    }
+
+    #[test]
+    fn factored_display() {
+        let factored = |name: &str, basis: Basis, query: &str| {
+            let formatter = formatter_by_name(name).unwrap();
+            basis.format(&poly(query), formatter, CoeffStyle::Fraction, true)
+        };
+        let cubic = "x^3/6 + x^2/2 + x/3";
+        assert_eq!(
+            factored("plain", Basis::Standard, cubic),
+            "(x^3 + 3*x^2 + 2*x)/6"
+        );
+        assert_eq!(
+            factored("latex", Basis::Standard, cubic),
+            r"\frac{x^{3} + 3x^{2} + 2x}{6}"
+        );
+        assert_eq!(
+            factored("unicode", Basis::Standard, cubic),
+            "(x³ + 3x² + 2x)/6"
+        );
+        assert_eq!(
+            factored("sympy", Basis::Standard, cubic),
+            "Rational(1,6)*(x**3 + 3*x**2 + 2*x)"
+        );
+        assert_eq!(
+            factored("plain", Basis::Binomial, cubic),
+            "C(x,3) + 2*C(x,2) + x"
+        );
+
+        let negative = "-4/3*x^2 + 2/3";
+        assert_eq!(
+            factored("plain", Basis::Standard, negative),
+            "-2*(2*x^2 - 1)/3"
+        );
+        assert_eq!(
+            factored("latex", Basis::Standard, negative),
+            r"-\frac{2\left(2x^{2} - 1\right)}{3}"
+        );
+        assert_eq!(
+            factored("plain", Basis::Binomial, "6*C(x, 3) - 9*C(x, 1)"),
+            "3*(2*C(x,3) - 3*x)"
+        );
+
+        // Nothing to factor out of a single term, a primitive polynomial or zero.
+        assert_eq!(factored("plain", Basis::Standard, "-3/2*x^4"), "-(3/2)*x^4");
+        assert_eq!(factored("plain", Basis::Standard, "x^2 - 2"), "x^2 - 2");
+        assert_eq!(factored("plain", Basis::Standard, "0"), "0");
+        // The Rust listing is never factored.
+        let rust = formatter_by_name("rust").unwrap();
+        let listing = Basis::Standard.format(&poly(cubic), rust, CoeffStyle::Fraction, false);
+        assert_eq!(factored("rust", Basis::Standard, cubic), listing);
+    }
+
+    #[test]
+    fn rust_numbers_are_exact_expressions() {
+        let rust = formatter_by_name("rust").unwrap();
+        let number = |n: i64, d: i64| {
+            let value = BigRational::new(n.into(), d.into());
+            super::format_number(&value, rust, CoeffStyle::Decimal)
+        };
+        assert_eq!(number(-5, 2), "-BigRational::new(5.into(), 2.into())");
+        assert_eq!(number(0, 1), "BigRational::from_integer(0.into())");
+        assert_eq!(
+            number(10_000_000_000, 3),
+            r#""10000000000/3".parse::<BigRational>().unwrap()"#
+        );
+    }
+}
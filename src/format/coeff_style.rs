@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Zero};
+
+/// Decimal expansions whose repeating part would be longer than this are shown as fractions.
+pub(super) const MAX_DECIMAL_DIGITS: usize = 100;
+
+// --- Coefficient Display Styles ---
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum CoeffStyle {
+    /// `7/3`
+    Fraction,
+    /// `2 1/3`
+    Mixed,
+    /// `2.(3)`, with the repeating part in parentheses
+    Decimal,
+    /// `2.333`, rounded to the given number of places
+    Approximate(usize),
+}
+
+/// A non-negative rational broken into the pieces a formatter needs to render it in some `CoeffStyle`.
+pub(crate) enum Number {
+    Integer(BigInt),
+    Fraction {
+        numer: BigInt,
+        denom: BigInt,
+    },
+    Mixed {
+        whole: BigInt,
+        numer: BigInt,
+        denom: BigInt,
+    },
+    Decimal {
+        whole: BigInt,
+        fixed: String,
+        repeating: String,
+    },
+}
+
+impl CoeffStyle {
+    pub(crate) fn split(self, abs_coeff: &BigRational) -> Number {
+        if abs_coeff.is_integer() {
+            return Number::Integer(abs_coeff.to_integer());
+        }
+        let fraction = || Number::Fraction {
+            numer: abs_coeff.numer().clone(),
+            denom: abs_coeff.denom().clone(),
+        };
+        match self {
+            CoeffStyle::Fraction => fraction(),
+            CoeffStyle::Mixed => {
+                let (whole, numer) = abs_coeff.numer().div_rem(abs_coeff.denom());
+                if whole.is_zero() {
+                    fraction()
+                } else {
+                    Number::Mixed {
+                        whole,
+                        numer,
+                        denom: abs_coeff.denom().clone(),
+                    }
+                }
+            }
+            CoeffStyle::Decimal => decimal_expansion(abs_coeff).unwrap_or_else(fraction),
+            CoeffStyle::Approximate(places) => rounded_decimal(abs_coeff, places),
+        }
+    }
+}
+
+/// Long division, remembering each remainder to find where the digits start repeating.
+fn decimal_expansion(abs_coeff: &BigRational) -> Option<Number> {
+    let denom = abs_coeff.denom();
+    let (whole, mut remainder) = abs_coeff.numer().div_rem(denom);
+    let mut digits = String::new();
+    let mut seen = HashMap::new();
+    while !remainder.is_zero() {
+        if let Some(start) = seen.insert(remainder.clone(), digits.len()) {
+            let repeating = digits.split_off(start);
+            return Some(Number::Decimal {
+                whole,
+                fixed: digits,
+                repeating,
+            });
+        }
+        if digits.len() >= MAX_DECIMAL_DIGITS {
+            return None;
+        }
+        let (digit, rem) = (remainder * BigInt::from(10)).div_rem(denom);
+        digits.push_str(&digit.to_string());
+        remainder = rem;
+    }
+    Some(Number::Decimal {
+        whole,
+        fixed: digits,
+        repeating: String::new(),
+    })
+}
+
+fn rounded_decimal(abs_coeff: &BigRational, places: usize) -> Number {
+    let scale = BigInt::from(10).pow(places as u32);
+    let half = BigRational::new(BigInt::one(), BigInt::from(2));
+    let scaled = (abs_coeff * &scale + half).floor().to_integer();
+    let (whole, frac) = scaled.div_rem(&scale);
+    let fixed = if places == 0 {
+        String::new()
+    } else {
+        format!("{:0>width$}", frac, width = places)
+    };
+    Number::Decimal {
+        whole,
+        fixed,
+        repeating: String::new(),
+    }
+}
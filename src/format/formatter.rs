@@ -0,0 +1,548 @@
+use std::fmt;
+
+use num_rational::BigRational;
+use num_traits::{One, Zero};
+
+use super::coeff_style::{CoeffStyle, Number};
+use crate::basis::Basis;
+
+// --- Output Formatters ---
+
+/// A target notation for rendering polynomials, used by `format_from_coeffs`.
+pub(crate) trait Formatter {
+    /// The name `by_name` finds this formatter under.
+    fn name(&self) -> &'static str;
+
+    /// Formats a non-negative constant on its own. Formatters that emit code
+    /// ignore `style` so that the output stays exact.
+    fn constant(&self, abs_coeff: &BigRational, style: CoeffStyle) -> String;
+
+    /// Formats a positive coefficient applied to a (non-constant) term.
+    fn scaled(&self, abs_coeff: &BigRational, style: CoeffStyle, term: &str) -> String;
+
+    fn variable(&self) -> String {
+        "x".to_string()
+    }
+
+    /// Formats `x^degree` for `degree >= 2`.
+    fn power(&self, degree: usize) -> String;
+
+    /// Formats `C(x, degree)` for `degree >= 2`.
+    fn binomial(&self, degree: usize) -> String;
+
+    /// Formats a positive content multiplying a parenthesized polynomial.
+    fn factored(&self, abs_content: &BigRational, inner: &str) -> String {
+        self.scaled(abs_content, CoeffStyle::Fraction, &format!("({})", inner))
+    }
+
+    fn minus(&self) -> &'static str {
+        "-"
+    }
+
+    fn zero(&self) -> String {
+        "0".to_string()
+    }
+
+    /// Whether the output is code for another system rather than notation.
+    fn emits_code(&self) -> bool {
+        false
+    }
+
+    /// Writes `poly` as a result computed modulo a prime.
+    fn modular(&self, poly: &str, modulus: u64) -> String {
+        format!("{} (mod {})", poly, modulus)
+    }
+
+    /// Whether `listing` writes whole polynomials.
+    fn emits_listing(&self) -> bool {
+        false
+    }
+
+    /// Writes a whole polynomial from its terms in `basis`, lowest first, for
+    /// formatters whose output isn't a sum of terms. The coefficients are residues
+    /// when `modulus` is given. `None` falls back to `format_from_coeffs`.
+    fn listing(
+        &self,
+        _terms: &[(usize, BigRational)],
+        _basis: Basis,
+        _modulus: Option<u64>,
+    ) -> Option<String> {
+        None
+    }
+}
+
+/// Looks up a formatter by the name used in the UI's selectors.
+pub(crate) fn by_name(name: &str) -> Option<&'static dyn Formatter> {
+    match name {
+        "plain" => Some(&Plain),
+        "latex" => Some(&Latex),
+        "unicode" => Some(&Unicode),
+        "sympy" => Some(&SymPy),
+        "mathematica" => Some(&Mathematica),
+        "maxima" => Some(&Maxima),
+        "rust" => Some(&Rust),
+        _ => None,
+    }
+}
+
+/// Formats `abs_coeff * term` the way most infix languages would: the
+/// coefficient is dropped when it is one and parenthesized when it is a fraction.
+fn scaled_infix(coeff_str: String, abs_coeff: &BigRational, term: &str, times: &str) -> String {
+    if abs_coeff.is_one() {
+        term.to_string()
+    } else if coeff_str.contains(['/', ' ']) {
+        format!("({}){}{}", coeff_str, times, term)
+    } else {
+        format!("{}{}{}", coeff_str, times, term)
+    }
+}
+
+/// Writes `abs_content * (inner)` as `numer*(inner)/denom`, leaving out whichever
+/// of the two is one.
+fn factored_infix(abs_content: &BigRational, inner: &str, times: &str) -> String {
+    let mut result = format!("({})", inner);
+    if !abs_content.numer().is_one() {
+        result = format!("{}{}{}", abs_content.numer(), times, result);
+    }
+    if !abs_content.denom().is_one() {
+        result = format!("{}/{}", result, abs_content.denom());
+    }
+    result
+}
+
+/// The calculator's own input syntax, e.g. `(1/2)*x^2 + 3*C(x,2)`.
+pub(crate) struct Plain;
+
+impl Formatter for Plain {
+    fn name(&self) -> &'static str {
+        "plain"
+    }
+
+    fn constant(&self, abs_coeff: &BigRational, style: CoeffStyle) -> String {
+        match style.split(abs_coeff) {
+            Number::Integer(n) => n.to_string(),
+            Number::Fraction { numer, denom } => format!("{}/{}", numer, denom),
+            Number::Mixed {
+                whole,
+                numer,
+                denom,
+            } => format!("{} {}/{}", whole, numer, denom),
+            Number::Decimal {
+                whole,
+                fixed,
+                repeating,
+            } => plain_decimal(&whole, &fixed, &repeating),
+        }
+    }
+
+    fn scaled(&self, abs_coeff: &BigRational, style: CoeffStyle, term: &str) -> String {
+        scaled_infix(self.constant(abs_coeff, style), abs_coeff, term, "*")
+    }
+
+    fn factored(&self, abs_content: &BigRational, inner: &str) -> String {
+        factored_infix(abs_content, inner, "*")
+    }
+
+    fn power(&self, degree: usize) -> String {
+        format!("x^{}", degree)
+    }
+
+    fn binomial(&self, degree: usize) -> String {
+        format!("C(x,{})", degree)
+    }
+}
+
+fn plain_decimal(whole: impl fmt::Display, fixed: &str, repeating: &str) -> String {
+    match (fixed.is_empty(), repeating.is_empty()) {
+        (true, true) => whole.to_string(),
+        (_, true) => format!("{}.{}", whole, fixed),
+        (_, false) => format!("{}.{}({})", whole, fixed, repeating),
+    }
+}
+
+/// LaTeX math mode, e.g. `\frac{1}{2}x^{2} + 3\binom{x}{2}`.
+pub(crate) struct Latex;
+
+impl Formatter for Latex {
+    fn name(&self) -> &'static str {
+        "latex"
+    }
+
+    fn constant(&self, abs_coeff: &BigRational, style: CoeffStyle) -> String {
+        match style.split(abs_coeff) {
+            Number::Integer(n) => n.to_string(),
+            Number::Fraction { numer, denom } => format!("\\frac{{{}}}{{{}}}", numer, denom),
+            Number::Mixed {
+                whole,
+                numer,
+                denom,
+            } => format!("{}\\frac{{{}}}{{{}}}", whole, numer, denom),
+            Number::Decimal {
+                whole,
+                fixed,
+                repeating,
+            } if !repeating.is_empty() => {
+                format!("{}.{}\\overline{{{}}}", whole, fixed, repeating)
+            }
+            Number::Decimal { whole, fixed, .. } => plain_decimal(&whole, &fixed, ""),
+        }
+    }
+
+    fn scaled(&self, abs_coeff: &BigRational, style: CoeffStyle, term: &str) -> String {
+        if abs_coeff.is_one() {
+            term.to_string()
+        } else {
+            format!("{}{}", self.constant(abs_coeff, style), term)
+        }
+    }
+
+    fn factored(&self, abs_content: &BigRational, inner: &str) -> String {
+        let numer = if abs_content.numer().is_one() {
+            inner.to_string()
+        } else {
+            format!("{}\\left({}\\right)", abs_content.numer(), inner)
+        };
+        if abs_content.denom().is_one() {
+            numer
+        } else {
+            format!("\\frac{{{}}}{{{}}}", numer, abs_content.denom())
+        }
+    }
+
+    fn modular(&self, poly: &str, modulus: u64) -> String {
+        format!("{} \\pmod{{{}}}", poly, modulus)
+    }
+
+    fn power(&self, degree: usize) -> String {
+        format!("x^{{{}}}", degree)
+    }
+
+    fn binomial(&self, degree: usize) -> String {
+        format!("\\binom{{x}}{{{}}}", degree)
+    }
+}
+
+/// Human-readable text, e.g. `¹⁄₂x² + 3⁽ˣ₂⁾`. Not parseable.
+pub(crate) struct Unicode;
+
+fn map_digits(n: impl fmt::Display, digits: &[char; 10]) -> String {
+    n.to_string()
+        .chars()
+        .map(|c| match c.to_digit(10) {
+            Some(d) => digits[d as usize],
+            None => c,
+        })
+        .collect()
+}
+
+fn superscript(n: impl fmt::Display) -> String {
+    map_digits(n, &['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'])
+}
+
+fn subscript(n: impl fmt::Display) -> String {
+    map_digits(n, &['₀', '₁', '₂', '₃', '₄', '₅', '₆', '₇', '₈', '₉'])
+}
+
+impl Formatter for Unicode {
+    fn name(&self) -> &'static str {
+        "unicode"
+    }
+
+    fn constant(&self, abs_coeff: &BigRational, style: CoeffStyle) -> String {
+        match style.split(abs_coeff) {
+            Number::Integer(n) => n.to_string(),
+            Number::Fraction { numer, denom } => {
+                format!("{}⁄{}", superscript(numer), subscript(denom))
+            }
+            Number::Mixed {
+                whole,
+                numer,
+                denom,
+            } => format!("{}{}⁄{}", whole, superscript(numer), subscript(denom)),
+            Number::Decimal {
+                whole,
+                fixed,
+                repeating,
+            } => plain_decimal(&whole, &fixed, &repeating),
+        }
+    }
+
+    fn scaled(&self, abs_coeff: &BigRational, style: CoeffStyle, term: &str) -> String {
+        if abs_coeff.is_one() {
+            term.to_string()
+        } else {
+            format!("{}{}", self.constant(abs_coeff, style), term)
+        }
+    }
+
+    fn factored(&self, abs_content: &BigRational, inner: &str) -> String {
+        factored_infix(abs_content, inner, "")
+    }
+
+    fn power(&self, degree: usize) -> String {
+        format!("x{}", superscript(degree))
+    }
+
+    fn binomial(&self, degree: usize) -> String {
+        format!("⁽ˣ{}⁾", subscript(degree))
+    }
+
+    fn minus(&self) -> &'static str {
+        "−"
+    }
+}
+
+/// Python with SymPy, e.g. `Rational(1,2)*x**2 + 3*binomial(x,2)`.
+pub(crate) struct SymPy;
+
+impl Formatter for SymPy {
+    fn name(&self) -> &'static str {
+        "sympy"
+    }
+
+    fn constant(&self, abs_coeff: &BigRational, _style: CoeffStyle) -> String {
+        if abs_coeff.is_integer() {
+            abs_coeff.to_string()
+        } else {
+            format!("Rational({},{})", abs_coeff.numer(), abs_coeff.denom())
+        }
+    }
+
+    fn scaled(&self, abs_coeff: &BigRational, style: CoeffStyle, term: &str) -> String {
+        if abs_coeff.is_one() {
+            term.to_string()
+        } else {
+            format!("{}*{}", self.constant(abs_coeff, style), term)
+        }
+    }
+
+    fn power(&self, degree: usize) -> String {
+        format!("x**{}", degree)
+    }
+
+    fn binomial(&self, degree: usize) -> String {
+        format!("binomial(x,{})", degree)
+    }
+
+    fn emits_code(&self) -> bool {
+        true
+    }
+
+    fn modular(&self, poly: &str, modulus: u64) -> String {
+        format!("Poly({}, x, modulus={})", poly, modulus)
+    }
+}
+
+/// Mathematica / Wolfram Language, e.g. `(1/2)*x^2 + 3*Binomial[x,2]`.
+pub(crate) struct Mathematica;
+
+impl Formatter for Mathematica {
+    fn name(&self) -> &'static str {
+        "mathematica"
+    }
+
+    fn constant(&self, abs_coeff: &BigRational, _style: CoeffStyle) -> String {
+        abs_coeff.to_string()
+    }
+
+    fn scaled(&self, abs_coeff: &BigRational, style: CoeffStyle, term: &str) -> String {
+        scaled_infix(self.constant(abs_coeff, style), abs_coeff, term, "*")
+    }
+
+    fn power(&self, degree: usize) -> String {
+        format!("x^{}", degree)
+    }
+
+    fn binomial(&self, degree: usize) -> String {
+        format!("Binomial[x,{}]", degree)
+    }
+
+    fn emits_code(&self) -> bool {
+        true
+    }
+
+    fn modular(&self, poly: &str, modulus: u64) -> String {
+        format!("PolynomialMod[{}, {}]", poly, modulus)
+    }
+}
+
+/// Maxima, e.g. `(1/2)*x^2 + 3*binomial(x,2)`.
+pub(crate) struct Maxima;
+
+impl Formatter for Maxima {
+    fn name(&self) -> &'static str {
+        "maxima"
+    }
+
+    fn constant(&self, abs_coeff: &BigRational, _style: CoeffStyle) -> String {
+        abs_coeff.to_string()
+    }
+
+    fn scaled(&self, abs_coeff: &BigRational, style: CoeffStyle, term: &str) -> String {
+        scaled_infix(self.constant(abs_coeff, style), abs_coeff, term, "*")
+    }
+
+    fn power(&self, degree: usize) -> String {
+        format!("x^{}", degree)
+    }
+
+    fn binomial(&self, degree: usize) -> String {
+        format!("binomial(x,{})", degree)
+    }
+
+    fn emits_code(&self) -> bool {
+        true
+    }
+
+    fn modular(&self, poly: &str, modulus: u64) -> String {
+        format!("polymod({}, {})", poly, modulus)
+    }
+}
+
+/// Rust needing only the num crates: the terms as a constant array, and a function
+/// evaluating them exactly. Lone numbers are `BigRational` expressions.
+pub(crate) struct Rust;
+
+impl Rust {
+    fn rational(abs_coeff: &BigRational) -> String {
+        // Unsuffixed literals are i32.
+        if abs_coeff.numer().bits() < 32 && abs_coeff.denom().bits() < 32 {
+            format!(
+                "BigRational::new({}.into(), {}.into())",
+                abs_coeff.numer(),
+                abs_coeff.denom()
+            )
+        } else {
+            format!("\"{}\".parse::<BigRational>().unwrap()", abs_coeff)
+        }
+    }
+}
+
+/// Sums `c * x^k` over the terms `(k, c)` of a `Rust` listing.
+const RUST_STANDARD_EVAL: &str = "\
+fn p(x: &BigRational) -> BigRational {
+    TERMS
+        .iter()
+        .map(|&(k, c)| c.parse::<BigRational>().unwrap() * x.pow(k))
+        .sum()
+}";
+
+/// Sums `c * C(x, k)` over the terms `(k, c)` of a `Rust` listing.
+const RUST_BINOMIAL_EVAL: &str = "\
+fn p(x: &BigRational) -> BigRational {
+    let one = BigRational::from_integer(1.into());
+    TERMS
+        .iter()
+        .map(|&(k, c)| {
+            // C(x, k) = x/1 * (x - 1)/2 * ... * (x - k + 1)/k
+            let binomial = (0..k).fold(one.clone(), |b, i| {
+                b * (x - BigRational::from_integer(i.into())) / BigRational::from_integer((i + 1).into())
+            });
+            c.parse::<BigRational>().unwrap() * binomial
+        })
+        .sum()
+}";
+
+/// Sums `c * x^k` mod `MODULUS` over the terms `(k, c)` of a `Rust` listing.
+/// Residues are below 2^32, so no product overflows.
+const RUST_MODULAR_EVAL: &str = "\
+fn p(x: u64) -> u64 {
+    let x = x % MODULUS;
+    TERMS.iter().fold(0, |sum, &(k, c)| {
+        let power = (0..k).fold(1, |power, _| power * x % MODULUS);
+        (sum + c * power) % MODULUS
+    })
+}";
+
+impl Formatter for Rust {
+    fn name(&self) -> &'static str {
+        "rust"
+    }
+
+    fn constant(&self, abs_coeff: &BigRational, _style: CoeffStyle) -> String {
+        Rust::rational(abs_coeff)
+    }
+
+    fn scaled(&self, abs_coeff: &BigRational, _style: CoeffStyle, term: &str) -> String {
+        if abs_coeff.is_one() {
+            term.to_string()
+        } else {
+            format!("{} * {}", term, Rust::rational(abs_coeff))
+        }
+    }
+
+    fn variable(&self) -> String {
+        "x.clone()".to_string()
+    }
+
+    fn power(&self, degree: usize) -> String {
+        format!("x.pow({})", degree)
+    }
+
+    fn binomial(&self, degree: usize) -> String {
+        format!(
+            "(0..{}).fold(BigRational::from_integer(1.into()), |b, i| \
+             b * (x - BigRational::from_integer(i.into())) / BigRational::from_integer((i + 1).into()))",
+            degree
+        )
+    }
+
+    fn zero(&self) -> String {
+        "BigRational::from_integer(0.into())".to_string()
+    }
+
+    fn emits_code(&self) -> bool {
+        true
+    }
+
+    fn modular(&self, poly: &str, modulus: u64) -> String {
+        format!("{} /* mod {} */", poly, modulus)
+    }
+
+    fn emits_listing(&self) -> bool {
+        true
+    }
+
+    fn listing(
+        &self,
+        terms: &[(usize, BigRational)],
+        basis: Basis,
+        modulus: Option<u64>,
+    ) -> Option<String> {
+        let terms: Vec<&(usize, BigRational)> =
+            terms.iter().rev().filter(|(_, c)| !c.is_zero()).collect();
+        let listing = match modulus {
+            Some(modulus) => {
+                let terms: Vec<String> = (terms.iter())
+                    .map(|(k, c)| format!("({}, {})", k, c))
+                    .collect();
+                format!(
+                    "// p(x) = sum of c * x^k mod MODULUS over TERMS (k, c)\n\
+                     const MODULUS: u64 = {};\n\
+                     const TERMS: &[(u64, u64)] = &[{}];\n\n{}\n",
+                    modulus,
+                    terms.join(", "),
+                    RUST_MODULAR_EVAL
+                )
+            }
+            None => {
+                let (term, eval) = match basis {
+                    Basis::Standard => ("x^k", RUST_STANDARD_EVAL),
+                    Basis::Binomial => ("C(x, k)", RUST_BINOMIAL_EVAL),
+                };
+                let terms: Vec<String> = (terms.iter())
+                    .map(|(k, c)| format!("({}, \"{}\")", k, c))
+                    .collect();
+                format!(
+                    "// p(x) = sum of c * {} over TERMS (k, c), exactly\n\
+                     use num_rational::BigRational;\n\n\
+                     const TERMS: &[(i32, &str)] = &[{}];\n\n{}\n",
+                    term,
+                    terms.join(", "),
+                    eval
+                )
+            }
+        };
+        Some(listing)
+    }
+}
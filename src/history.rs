@@ -0,0 +1,276 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::basis::Basis;
+use crate::format::CoeffStyle;
+use crate::parse::{Bindings, Parsed};
+use crate::worker::{decode_value, encode_value};
+
+pub(crate) const DEFAULT_CAP: usize = 10;
+pub(crate) const MAX_CAP: usize = 1000;
+
+// --- History Entries ---
+/// Everything the formatted value of an entry depends on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct DisplayKey {
+    pub(crate) basis: Basis,
+    pub(crate) formatter: &'static str,
+    pub(crate) coeff_style: CoeffStyle,
+    pub(crate) factor_content: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HistoryEntry {
+    /// Identifies the entry for its whole lifetime; assigned by `History::push`.
+    pub(crate) id: u64,
+    pub(crate) query: String,
+    /// The result as first shown. Only displayed for queries that failed.
+    pub(crate) result: String,
+    /// What the query evaluated to, if it succeeded.
+    pub(crate) value: Option<Parsed>,
+    /// Pinned entries are listed first and never evicted by the cap.
+    pub(crate) pinned: bool,
+    /// Formatted values, memoized per display setting.
+    formatted: RefCell<HashMap<DisplayKey, String>>,
+}
+
+impl HistoryEntry {
+    pub(crate) fn new(query: String, result: String, value: Option<Parsed>) -> Self {
+        HistoryEntry {
+            query,
+            result,
+            value,
+            ..HistoryEntry::default()
+        }
+    }
+
+    /// The value formatted by `format`, which is only called once per `key`.
+    pub(crate) fn formatted(
+        &self,
+        key: DisplayKey,
+        format: impl FnOnce(&Parsed) -> String,
+    ) -> Option<String> {
+        let value = self.value.as_ref()?;
+        let mut formatted = self.formatted.borrow_mut();
+        Some(
+            formatted
+                .entry(key)
+                .or_insert_with(|| format(value))
+                .clone(),
+        )
+    }
+
+    /// Case-insensitive substring match on the query or the result.
+    pub(crate) fn matches(&self, filter: &str) -> bool {
+        let filter = filter.to_lowercase();
+        self.query.to_lowercase().contains(&filter) || self.result.to_lowercase().contains(&filter)
+    }
+}
+
+/// Past queries, oldest first, with at most `cap` unpinned entries.
+#[derive(Debug, Clone)]
+pub(crate) struct History {
+    entries: Vec<HistoryEntry>,
+    cap: usize,
+    next_id: u64,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new(DEFAULT_CAP)
+    }
+}
+
+impl History {
+    pub(crate) fn new(cap: usize) -> Self {
+        History {
+            entries: Vec::new(),
+            cap: cap.clamp(1, MAX_CAP),
+            next_id: 0,
+        }
+    }
+
+    pub(crate) fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// The position of the entry with the given `id`, if it's still there.
+    pub(crate) fn index_of(&self, id: u64) -> Option<usize> {
+        self.entries.iter().position(|e| e.id == id)
+    }
+
+    /// The values `ans`, `ans1`, `ans2`, ... refer to, most recent first.
+    pub(crate) fn bindings(&self) -> Bindings<'_> {
+        Bindings::new(
+            self.entries
+                .iter()
+                .rev()
+                .map(|e| e.value.as_ref())
+                .collect(),
+        )
+    }
+
+    /// The name of the entry at `index` in `bindings`, e.g. `ans3`.
+    pub(crate) fn answer_name(&self, index: usize) -> String {
+        format!("ans{}", self.entries.len() - index)
+    }
+
+    pub(crate) fn cap(&self) -> usize {
+        self.cap
+    }
+
+    pub(crate) fn push(&mut self, mut entry: HistoryEntry) {
+        entry.id = self.next_id;
+        self.next_id += 1;
+        self.entries.push(entry);
+        self.evict();
+    }
+
+    pub(crate) fn set_cap(&mut self, cap: usize) {
+        self.cap = cap.clamp(1, MAX_CAP);
+        self.evict();
+    }
+
+    pub(crate) fn toggle_pin(&mut self, index: usize) {
+        if let Some(entry) = self.entries.get_mut(index) {
+            entry.pinned = !entry.pinned;
+        }
+        self.evict();
+    }
+
+    /// Drops the oldest unpinned entries until at most `cap` are left.
+    fn evict(&mut self) {
+        let unpinned = self.entries.iter().filter(|e| !e.pinned).count();
+        let mut excess = unpinned.saturating_sub(self.cap);
+        self.entries.retain(|e| {
+            if excess > 0 && !e.pinned {
+                excess -= 1;
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    // --- Serialization ---
+    /// One line per entry: a pin flag, the query, the result and the value as the
+    /// worker encodes it, separated by tabs. Reading a value back then needs no
+    /// evaluation, and the outcome of a comparison survives too.
+    pub(crate) fn serialize(&self) -> String {
+        self.entries
+            .iter()
+            .map(|e| {
+                let pin = if e.pinned { "*" } else { "-" };
+                let value = e.value.as_ref().map(encode_value).unwrap_or_default();
+                format!(
+                    "{}\t{}\t{}\t{}",
+                    pin,
+                    escape(&e.query),
+                    escape(&e.result),
+                    escape(&value)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Reads the output of `serialize`, skipping malformed lines.
+    pub(crate) fn deserialize(s: &str, cap: usize) -> Self {
+        let mut history = History::new(cap);
+        let entries = s
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                let pinned = fields.next()? == "*";
+                let query = unescape(fields.next()?);
+                let result = unescape(fields.next()?);
+                let value = unescape(fields.next()?);
+                let value = decode_value(&value);
+                Some(HistoryEntry {
+                    pinned,
+                    ..HistoryEntry::new(query, result, value)
+                })
+            })
+            .collect::<Vec<_>>();
+        for entry in entries {
+            history.push(entry);
+        }
+        history
+    }
+}
+
+/// Writes backslashes, tabs and line breaks as `\\`, `\t`, `\n` and `\r`, so that
+/// `s` fits in one field of a line.
+pub(crate) fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The inverse of `escape`.
+pub(crate) fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{History, HistoryEntry};
+    use crate::budget::Budget;
+    use crate::parse::{parse, Associativity, Bindings};
+
+    #[test]
+    fn values_survive_a_reload() {
+        let mut history = History::default();
+        for query in [
+            "x^2/3 - 1",
+            "mod 7: (x + 1)^7",
+            "x^2 == x",
+            "C(x, 2) == x*(x-1)/2",
+            "1/0",
+        ] {
+            let value = parse(
+                query,
+                &Bindings::default(),
+                &Budget::default(),
+                Associativity::Right,
+            );
+            history.push(HistoryEntry::new(
+                query.to_string(),
+                String::new(),
+                value.ok(),
+            ));
+        }
+        let reloaded = History::deserialize(&history.serialize(), history.cap());
+        let values = |h: &History| {
+            h.entries()
+                .iter()
+                .map(|e| e.value.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values(&reloaded), values(&history));
+        assert!(reloaded.entries()[4].value.is_none());
+    }
+}
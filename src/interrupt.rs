@@ -0,0 +1,41 @@
+use std::cell::{Cell, RefCell};
+
+// --- Cooperative Cancellation ---
+// Long loops in the core call `checkpoint` and stop early once it returns true.
+// Whatever they return at that point is garbage; `parse` turns the interruption
+// into an error so it never reaches the user.
+
+thread_local! {
+    static CHECK: RefCell<Option<Box<dyn Fn() -> bool>>> = const { RefCell::new(None) };
+    static INTERRUPTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Installs the test `checkpoint` polls for a cancellation request.
+pub(crate) fn set_check(check: impl Fn() -> bool + 'static) {
+    CHECK.with(|c| *c.borrow_mut() = Some(Box::new(check)));
+}
+
+/// Whether the current computation should stop. Once it has said yes, it keeps
+/// saying yes until `reset`.
+pub(crate) fn checkpoint() -> bool {
+    if INTERRUPTED.get() {
+        return true;
+    }
+    let cancelled = CHECK.with(|c| c.borrow().as_ref().is_some_and(|check| check()));
+    INTERRUPTED.set(cancelled);
+    cancelled
+}
+
+/// Starts a new computation with no interruption recorded.
+pub(crate) fn reset() {
+    INTERRUPTED.set(false);
+}
+
+/// Fails if the computation was interrupted since the last `reset`.
+pub(crate) fn check() -> Result<(), String> {
+    if INTERRUPTED.get() {
+        Err("Cancelled".to_string())
+    } else {
+        Ok(())
+    }
+}
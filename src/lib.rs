@@ -1,120 +1,837 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
+use js_sys::Date;
 use num_rational::BigRational;
-use num_traits::Zero;
+use num_traits::One;
 use wasm_bindgen::{prelude::*, JsCast};
-use web_sys::{Element, HtmlInputElement, HtmlSelectElement, HtmlTextAreaElement, KeyboardEvent};
+use web_sys::{
+    Document, Element, Event, HtmlInputElement, HtmlSelectElement, HtmlTextAreaElement,
+    KeyboardEvent, Storage,
+};
 
 mod basis;
+mod budget;
 mod format;
+mod history;
+mod interrupt;
+mod multiply;
+mod multipoint;
 mod parse;
 mod pascal;
+mod permalink;
+mod plot;
 mod polynomial;
+mod ring;
+mod trace;
+mod worker;
 
-use crate::basis::Basis;
-use crate::parse::parse;
+use crate::basis::{Basis, Integrality};
+use crate::budget::Budget;
+use crate::format::{format_number, formatter_by_name, CoeffStyle, Formatter, Plain, Unicode};
+use crate::history::{DisplayKey, History, HistoryEntry};
+use crate::parse::{
+    parse, parse_eval_input, parse_to_ast, Associativity, EvalInput, EvalPoints, Identity, Parsed,
+};
+use crate::permalink::Session;
+use crate::plot::{plot_svg, Viewport};
 use crate::polynomial::Polynomial;
-
-struct HistoryEntry {
-    query: String,
-    result: String,
-}
+use crate::trace::Trace;
+use crate::worker::{CalcWorker, Request, RequestId, Response};
 
 struct AppState {
     // Calculator State
-    history: Vec<HistoryEntry>,
-    current_poly: Option<Polynomial>,
+    history: History,
+    history_filter: String,
+    current_poly: Option<Parsed>,
+    /// What `render_annotation` cites for `current_poly`, computed along with it.
+    current_integrality: Option<Integrality>,
     basis: Basis,
+    formatter: &'static dyn Formatter,
+    coeff_style: CoeffStyle,
+    factor_content: bool,
+    plot_view: Viewport,
+    budget: Budget,
+    powers: Associativity,
+    show_work: bool,
+    /// The rows of the value table under the evaluation input, as shown.
+    eval_table: Vec<[String; 2]>,
+    // Background Calculation
+    worker: Option<CalcWorker>,
+    pending: Option<Pending>,
+    next_request_id: RequestId,
 }
 
-fn update_history_display(history: &[HistoryEntry], history_list_element: &Element, basis: Basis) {
-    let document = web_sys::window().unwrap().document().unwrap();
-    history_list_element.set_inner_html("");
+/// A calculation running in the worker.
+struct Pending {
+    id: RequestId,
+    query: String,
+    record: bool,
+    _ticker: Ticker,
+}
+
+/// Calls a closure at a fixed interval until dropped.
+struct Ticker {
+    handle: i32,
+    _callback: Closure<dyn FnMut()>,
+}
 
-    for entry in history.iter().rev() {
-        let entry_div = document.create_element("div").unwrap();
-        entry_div.set_class_name("history-entry");
+impl Ticker {
+    fn start(interval_ms: i32, callback: impl FnMut() + 'static) -> Self {
+        let callback = Closure::<dyn FnMut()>::new(callback);
+        let handle = web_sys::window()
+            .unwrap()
+            .set_interval_with_callback_and_timeout_and_arguments_0(
+                callback.as_ref().unchecked_ref(),
+                interval_ms,
+            )
+            .unwrap();
+        Ticker {
+            handle,
+            _callback: callback,
+        }
+    }
+}
 
-        let query_div = document.create_element("div").unwrap();
-        query_div.set_class_name("history-query");
-        query_div.set_text_content(Some(&entry.query));
+impl Drop for Ticker {
+    fn drop(&mut self) {
+        web_sys::window()
+            .unwrap()
+            .clear_interval_with_handle(self.handle);
+    }
+}
 
-        let result_div = document.create_element("div").unwrap();
-        result_div.set_class_name("history-result");
-        // Re-parse the original query to format it in the current basis
-        if let Ok(poly) = parse(&entry.query) {
-            result_div.set_text_content(Some(&basis.format(&poly)));
+impl AppState {
+    fn display_key(&self) -> DisplayKey {
+        DisplayKey {
+            basis: self.basis,
+            formatter: self.formatter.name(),
+            coeff_style: self.coeff_style,
+            factor_content: self.factor_content,
+        }
+    }
+
+    fn format(&self, parsed: &Parsed) -> String {
+        self.format_with(parsed, self.formatter)
+    }
+
+    fn format_with(&self, parsed: &Parsed, formatter: &dyn Formatter) -> String {
+        match parsed {
+            Parsed::Rational(poly) => {
+                self.basis
+                    .format(poly, formatter, self.coeff_style, self.factor_content)
+            }
+            Parsed::Modular { poly, modulus } => {
+                self.basis.format_modular(poly, *modulus, formatter)
+            }
+            Parsed::Identity(identity) => self.describe_identity(identity, formatter),
+        }
+    }
+
+    fn describe_identity(&self, identity: &Identity, formatter: &dyn Formatter) -> String {
+        if identity.holds {
+            return "true".to_string();
+        }
+        let Some(witness) = &identity.witness else {
+            return "false: the sides are different polynomials, though equal at every point of the field"
+                .to_string();
+        };
+        let format = |value: &BigRational| format_number(value, formatter, self.coeff_style);
+        format!(
+            "false: at x = {} the left side is {} and the right side is {}",
+            format(&witness.x),
+            format(&witness.lhs),
+            format(&witness.rhs)
+        )
+    }
+}
+
+/// Brings the history list in line with `app_state.history`. Nodes of entries
+/// already shown are reused and moved only if their position changed, and only
+/// text that changed is rewritten.
+fn update_history_display(app_state: &AppState, history_list_element: &Element) {
+    let document = web_sys::window().unwrap().document().unwrap();
+    let children = history_list_element.children();
+    let mut existing: HashMap<u64, Element> = (0..children.length())
+        .filter_map(|i| children.item(i))
+        .filter_map(|node| Some((node.get_attribute("data-id")?.parse().ok()?, node)))
+        .collect();
+
+    // Pinned entries first, newest first within each group
+    let entries = app_state.history.entries();
+    let mut order: Vec<usize> = (0..entries.len()).rev().collect();
+    order.sort_by_key(|&i| !entries[i].pinned);
+    let mut cursor = history_list_element.first_element_child();
+    for index in order {
+        let entry = &entries[index];
+        let node = existing
+            .remove(&entry.id)
+            .unwrap_or_else(|| create_history_node(&document, entry));
+        update_history_node(&node, app_state, index);
+        if cursor.as_ref() == Some(&node) {
+            cursor = node.next_element_sibling();
         } else {
-            result_div.set_text_content(Some(&entry.result)); // Show original result if it was an error
+            history_list_element
+                .insert_before(&node, cursor.as_ref().map(AsRef::as_ref))
+                .unwrap();
+        }
+    }
+    // Whatever is left was evicted
+    for node in existing.into_values() {
+        node.remove();
+    }
+}
+
+fn create_history_node(document: &Document, entry: &HistoryEntry) -> Element {
+    let entry_div = document.create_element("div").unwrap();
+    entry_div
+        .set_attribute("data-id", &entry.id.to_string())
+        .unwrap();
+    entry_div
+        .set_attribute("title", "Click to load this query")
+        .unwrap();
+
+    let pin_button = document.create_element("button").unwrap();
+    pin_button.set_class_name("history-pin");
+
+    let query_div = document.create_element("div").unwrap();
+    query_div.set_class_name("history-query");
+    query_div.set_text_content(Some(&entry.query));
+
+    let result_div = document.create_element("div").unwrap();
+    result_div.set_class_name("history-result");
+
+    entry_div.append_child(&pin_button).unwrap();
+    entry_div.append_child(&query_div).unwrap();
+    entry_div.append_child(&result_div).unwrap();
+    entry_div
+}
+
+/// Updates the parts of a history node that depend on the entry's position,
+/// its pin, the filter or the display settings.
+fn update_history_node(node: &Element, app_state: &AppState, index: usize) {
+    let entry = &app_state.history.entries()[index];
+    let set_text = |element: Element, text: &str| {
+        if element.text_content().as_deref() != Some(text) {
+            element.set_text_content(Some(text));
         }
+    };
 
-        entry_div.append_child(&query_div).unwrap();
-        entry_div.append_child(&result_div).unwrap();
-        history_list_element.append_child(&entry_div).unwrap();
+    node.set_class_name(if entry.pinned {
+        "history-entry pinned"
+    } else {
+        "history-entry"
+    });
+    node.toggle_attribute_with_force("hidden", !entry.matches(&app_state.history_filter))
+        .unwrap();
+
+    let pin_button = node.query_selector(".history-pin").unwrap().unwrap();
+    set_text(pin_button.clone(), if entry.pinned { "★" } else { "☆" });
+    pin_button
+        .set_attribute("title", if entry.pinned { "Unpin" } else { "Pin" })
+        .unwrap();
+
+    // Format the stored value in the current basis, once per display setting
+    let result_div = node.query_selector(".history-result").unwrap().unwrap();
+    match entry.formatted(app_state.display_key(), |value| app_state.format(value)) {
+        Some(formatted) => set_text(
+            result_div,
+            &format!("{} = {}", app_state.history.answer_name(index), formatted),
+        ),
+        None => set_text(result_div, &entry.result), // Show original result if it was an error
     }
 }
 
 fn rerender_result(app_state: &AppState, result_output: &Element, history_list_element: &Element) {
     if let Some(poly) = &app_state.current_poly {
-        result_output.set_text_content(Some(&app_state.basis.format(poly)));
+        result_output.set_text_content(Some(&app_state.format(poly)));
     }
-    update_history_display(&app_state.history, history_list_element, app_state.basis);
+    update_history_display(app_state, history_list_element);
+    render_annotation(app_state);
+    render_plot(app_state);
 }
 
+fn render_annotation(app_state: &AppState) {
+    let document = web_sys::window().unwrap().document().unwrap();
+    let annotation = document.get_element_by_id("result-annotation").unwrap();
+    let text = match &app_state.current_integrality {
+        Some(integrality) => integrality_note(app_state, integrality),
+        None => String::new(),
+    };
+    annotation.set_text_content(Some(&text));
+}
+
+/// Says whether the current result is integer-valued, citing its binomial-basis
+/// coefficients.
+fn integrality_note(app_state: &AppState, integrality: &Integrality) -> String {
+    if !integrality.is_integer_valued() {
+        let (k, value) = integrality.witness().unwrap();
+        return format!(
+            "Not integer-valued: p({}) = {}. Multiplying by {} makes it integer-valued.",
+            k,
+            format_number(&value, app_state.formatter, app_state.coeff_style),
+            integrality.least_multiplier()
+        );
+    }
+    let certificate = integrality.format_certificate(app_state.formatter, app_state.coeff_style);
+    let mut note = format!(
+        "Integer-valued: {} has integer binomial coefficients.",
+        certificate
+    );
+    if !integrality.fixed_divisor().is_one() {
+        note += &format!(
+            " Every value is divisible by {}.",
+            integrality.fixed_divisor()
+        );
+    }
+    note
+}
+
+/// Lists the steps of the latest calculation, or hides the list if there are none.
+fn render_work(trace: &Trace) {
+    let document = web_sys::window().unwrap().document().unwrap();
+    let panel = document.get_element_by_id("work-panel").unwrap();
+    let list = document.get_element_by_id("work-steps").unwrap();
+    list.set_inner_html("");
+    for step in &trace.steps {
+        let item = document.create_element("li").unwrap();
+        item.set_text_content(Some(&step.forms.join(" = ")));
+        if let Some(rule) = &step.rule {
+            let note = document.create_element("span").unwrap();
+            note.set_class_name("work-rule");
+            note.set_text_content(Some(rule));
+            item.append_child(&note).unwrap();
+        }
+        list.append_child(&item).unwrap();
+    }
+    if trace.truncated {
+        let item = document.create_element("li").unwrap();
+        item.set_text_content(Some("… further steps left out"));
+        list.append_child(&item).unwrap();
+    }
+    panel
+        .toggle_attribute_with_force("hidden", trace.steps.is_empty())
+        .unwrap();
+}
+
+fn render_plot(app_state: &AppState) {
+    let document = web_sys::window().unwrap().document().unwrap();
+    let plot_output = document.get_element_by_id("plot-output").unwrap();
+    match &app_state.current_poly {
+        Some(Parsed::Rational(poly)) => {
+            plot_output.set_inner_html(&plot_svg(poly, app_state.plot_view))
+        }
+        _ => plot_output.set_inner_html(""),
+    }
+}
+
+/// Shows how `expression` groups, with every operation in parentheses. Inputs that
+/// don't parse show nothing here; the result says what's wrong.
+fn show_parsed_as(app_state: &AppState, expression: &str) {
+    let document = web_sys::window().unwrap().document().unwrap();
+    let parsed_as = document.get_element_by_id("parsed-as").unwrap();
+    let text = match parse_to_ast(expression, &app_state.budget, app_state.powers) {
+        Ok(statement) if !expression.is_empty() => format!("Parsed as: {:#}", statement),
+        _ => String::new(),
+    };
+    parsed_as.set_text_content(Some(&text));
+}
+
+/// Computes the expression in the input. Unless `record` is false, as when restoring a
+/// session on back/forward navigation, the result is added to the history and the URL.
 fn perform_calculation(
     input_element: &HtmlTextAreaElement,
     result_output: &Element,
     app_state: &mut AppState,
     history_list_element: &Element,
+    record: bool,
 ) {
+    cancel_calculation(app_state, result_output);
+    if record {
+        update_permalink(app_state);
+    }
     let expression_str = input_element.value();
+    show_parsed_as(app_state, &expression_str);
+    render_work(&Trace::default());
     if expression_str.is_empty() {
         result_output.set_text_content(Some("0"));
-        app_state.current_poly = Some(Polynomial::constant(BigRational::zero()));
+        app_state.current_poly = Some(Parsed::Rational(Polynomial::zero(())));
+        app_state.current_integrality = None;
         rerender_result(app_state, result_output, history_list_element);
         return;
     }
 
-    let (result_text, new_poly) = match parse(&expression_str) {
-        Ok(poly) => (app_state.basis.format(&poly), Some(poly)),
-        Err(e) => (format!("Error: {}", e), None),
+    let Some(worker) = &app_state.worker else {
+        // No worker available: compute right here, blocking the page
+        let (outcome, trace) = trace::record(app_state.show_work, || {
+            parse(
+                &expression_str,
+                &app_state.history.bindings(),
+                &app_state.budget,
+                app_state.powers,
+            )
+        });
+        let integrality = outcome.as_ref().ok().and_then(Integrality::of);
+        finish_calculation(
+            app_state,
+            record.then_some(expression_str),
+            outcome,
+            integrality,
+            &trace,
+            result_output,
+            history_list_element,
+        );
+        return;
     };
 
-    app_state.current_poly = new_poly;
+    app_state.next_request_id += 1;
+    let id = app_state.next_request_id;
+    worker.post(&Request {
+        id,
+        query: &expression_str,
+        budget: app_state.budget,
+        powers: app_state.powers,
+        trace: app_state.show_work,
+        answers: app_state.history.bindings().answers().to_vec(),
+    });
 
-    // We store the query, and the result as a string, but we'll re-format it live when basis changes
-    app_state.history.push(HistoryEntry {
+    // Show the elapsed time until the worker answers
+    let started = Date::now();
+    let result_clone = result_output.clone();
+    let show_elapsed = move || {
+        let seconds = (Date::now() - started) / 1000.0;
+        result_clone.set_text_content(Some(&format!("Computing… {:.1} s", seconds)));
+    };
+    show_elapsed();
+    result_output.class_list().add_1("computing").unwrap();
+    set_cancel_visible(true);
+    app_state.pending = Some(Pending {
+        id,
         query: expression_str,
-        result: result_text.clone(), // This is just for non-poly results like errors
+        record,
+        _ticker: Ticker::start(100, show_elapsed),
     });
-    if app_state.history.len() > 10 {
-        app_state.history.remove(0);
+}
+
+/// Handles a response from the worker, ignoring those for abandoned requests.
+fn receive_calculation(app_state: &mut AppState, response: Response) {
+    if app_state
+        .pending
+        .as_ref()
+        .is_none_or(|pending| pending.id != response.id)
+    {
+        return;
+    }
+    let pending = app_state.pending.take().unwrap();
+    let document = web_sys::window().unwrap().document().unwrap();
+    let result_output = document.get_element_by_id("result-output").unwrap();
+    let history_list_element = document.get_element_by_id("history-list").unwrap();
+    result_output.class_list().remove_1("computing").unwrap();
+    set_cancel_visible(false);
+    finish_calculation(
+        app_state,
+        pending.record.then_some(pending.query),
+        response.outcome,
+        response.integrality,
+        &response.trace,
+        &result_output,
+        &history_list_element,
+    );
+
+    // An evaluation requested meanwhile ran against the previous result
+    let eval_input = document
+        .get_element_by_id("eval-input")
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+    if !eval_input.value().is_empty() {
+        let eval_result = document.get_element_by_id("eval-result").unwrap();
+        perform_evaluation(&eval_input, &eval_result, app_state, pending.record);
+    }
+}
+
+/// Shows the outcome of a calculation, adding it to the history under `recorded_query`
+/// if that's given.
+fn finish_calculation(
+    app_state: &mut AppState,
+    recorded_query: Option<String>,
+    outcome: Result<Parsed, String>,
+    integrality: Option<Integrality>,
+    trace: &Trace,
+    result_output: &Element,
+    history_list_element: &Element,
+) {
+    let (result_text, new_poly) = match outcome {
+        Ok(poly) => (app_state.format(&poly), Some(poly)),
+        Err(e) => (format!("Error: {}", e), None),
+    };
+
+    app_state.current_poly = new_poly.clone();
+    app_state.current_integrality = integrality;
+
+    // We store the query, and the result as a string, but we'll re-format it live when basis changes
+    // The result string is just for non-poly results like errors
+    if let Some(query) = recorded_query {
+        app_state
+            .history
+            .push(HistoryEntry::new(query, result_text.clone(), new_poly));
+        save_settings(app_state);
     }
 
     rerender_result(app_state, result_output, history_list_element);
+    if app_state.current_poly.is_none() {
+        result_output.set_text_content(Some(&result_text));
+    }
+    render_work(trace);
 }
 
-fn perform_evaluation(eval_input: &HtmlInputElement, eval_result: &Element, app_state: &AppState) {
+/// Abandons the calculation running in the worker, if any.
+fn cancel_calculation(app_state: &mut AppState, result_output: &Element) {
+    let Some(pending) = app_state.pending.take() else {
+        return;
+    };
+    if let Some(worker) = &mut app_state.worker {
+        worker.cancel(pending.id);
+    }
+    result_output.class_list().remove_1("computing").unwrap();
+    result_output.set_text_content(Some("Cancelled"));
+    set_cancel_visible(false);
+}
+
+fn set_cancel_visible(visible: bool) {
+    let document = web_sys::window().unwrap().document().unwrap();
+    let cancel_button = document.get_element_by_id("cancel-button").unwrap();
+    cancel_button
+        .toggle_attribute_with_force("hidden", !visible)
+        .unwrap();
+}
+
+fn perform_evaluation(
+    eval_input: &HtmlInputElement,
+    eval_result: &Element,
+    app_state: &mut AppState,
+    record: bool,
+) {
+    if record {
+        update_permalink(app_state);
+    }
     let x_str = eval_input.value();
+    eval_result.set_inner_html("");
+    app_state.eval_table.clear();
+    set_table_copy_visible(false);
     if x_str.is_empty() {
-        eval_result.set_text_content(Some(""));
         return;
     }
 
-    let x_val = match x_str.parse::<BigRational>() {
-        Ok(val) => val,
-        Err(_) => {
-            eval_result.set_text_content(Some("Invalid number for x"));
+    let eval_input = match parse_eval_input(
+        &x_str,
+        &app_state.history.bindings(),
+        &app_state.budget,
+        app_state.powers,
+    ) {
+        Ok(eval_input) => eval_input,
+        Err(e) => {
+            eval_result.set_text_content(Some(&format!("Error: {}", e)));
             return;
         }
     };
 
-    if let Some(poly) = &app_state.current_poly {
-        let result = poly.eval(&x_val);
-        eval_result.set_text_content(Some(&result.to_string()));
-    } else {
+    let Some(poly) = &app_state.current_poly else {
         eval_result.set_text_content(Some("No valid polynomial to evaluate."));
+        return;
+    };
+    let points = match eval_input {
+        EvalInput::Points(points) => points,
+        EvalInput::Composition(arg) => {
+            let result_text = match poly.compose(&arg) {
+                Ok(composed) => app_state.format(&composed),
+                Err(e) => format!("Error: {}", e),
+            };
+            eval_result.set_text_content(Some(&result_text));
+            return;
+        }
+    };
+    let values = match poly.eval(&points) {
+        Ok(values) => values,
+        Err(e) => {
+            eval_result.set_text_content(Some(&format!("Error: {}", e)));
+            return;
+        }
+    };
+    let format =
+        |value: &BigRational| format_number(value, app_state.formatter, app_state.coeff_style);
+    if let EvalPoints::Single(_) = points {
+        eval_result.set_text_content(Some(&format(&values[0])));
+        return;
+    }
+
+    let rows: Vec<[String; 2]> = (points.xs().iter().zip(&values))
+        .map(|(x, value)| [format(x), format(value)])
+        .collect();
+    let document = web_sys::window().unwrap().document().unwrap();
+    let table = document.create_element("table").unwrap();
+    table.set_class_name("eval-table");
+    let header = document.create_element("tr").unwrap();
+    for label in EVAL_TABLE_HEADER {
+        let cell = document.create_element("th").unwrap();
+        cell.set_text_content(Some(label));
+        header.append_child(&cell).unwrap();
+    }
+    table.append_child(&header).unwrap();
+    for texts in &rows {
+        let row = document.create_element("tr").unwrap();
+        for text in texts {
+            let cell = document.create_element("td").unwrap();
+            cell.set_text_content(Some(text));
+            row.append_child(&cell).unwrap();
+        }
+        table.append_child(&row).unwrap();
+    }
+    eval_result.append_child(&table).unwrap();
+    app_state.eval_table = rows;
+    set_table_copy_visible(true);
+}
+
+const EVAL_TABLE_HEADER: [&str; 2] = ["x", "p(x)"];
+
+fn set_table_copy_visible(visible: bool) {
+    let document = web_sys::window().unwrap().document().unwrap();
+    let table_copy = document.get_element_by_id("table-copy").unwrap();
+    table_copy
+        .toggle_attribute_with_force("hidden", !visible)
+        .unwrap();
+}
+
+/// The value table as delimited text with a header row: CSV with `,`, TSV with a
+/// tab. CSV fields are quoted where they need it; TSV has no quoting, so tabs and
+/// line breaks inside a field become spaces.
+fn delimited_table(rows: &[[String; 2]], separator: char) -> String {
+    let field = |text: &str| {
+        if separator != ',' {
+            text.replace(['\t', '\n', '\r'], " ")
+        } else if text.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", text.replace('"', "\"\""))
+        } else {
+            text.to_string()
+        }
+    };
+    let header = EVAL_TABLE_HEADER.map(str::to_string);
+    let mut text = String::new();
+    for row in std::iter::once(&header).chain(rows) {
+        let fields: Vec<String> = row.iter().map(|cell| field(cell)).collect();
+        text.push_str(&fields.join(&separator.to_string()));
+        text.push('\n');
+    }
+    text
+}
+
+fn copy_table(app_state: &AppState, separator: char) {
+    if app_state.eval_table.is_empty() {
+        return;
+    }
+    let text = delimited_table(&app_state.eval_table, separator);
+    let clipboard = web_sys::window().unwrap().navigator().clipboard();
+    let _ = clipboard.write_text(&text);
+}
+
+fn copy_result(copy_format_selector: &HtmlSelectElement, app_state: &AppState) {
+    let Some(poly) = &app_state.current_poly else {
+        return;
+    };
+    let formatter = formatter_by_name(&copy_format_selector.value()).unwrap_or(&Plain);
+    let text = app_state.format_with(poly, formatter);
+    let clipboard = web_sys::window().unwrap().navigator().clipboard();
+    // The returned promise only reports success; there's nothing useful to do on failure.
+    let _ = clipboard.write_text(&text);
+}
+
+// --- Local Storage ---
+const HISTORY_KEY: &str = "polycalc.history";
+const HISTORY_CAP_KEY: &str = "polycalc.history-cap";
+const BASIS_KEY: &str = "polycalc.basis";
+const BUDGET_KEY: &str = "polycalc.budget";
+const POWERS_KEY: &str = "polycalc.powers";
+
+fn local_storage() -> Option<Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+/// Saves the history, the chosen basis, the limits and how `^` associates. Storage may be unavailable (e.g. in
+/// private browsing), in which case nothing persists.
+fn save_settings(app_state: &AppState) {
+    let Some(storage) = local_storage() else {
+        return;
+    };
+    let _ = storage.set_item(HISTORY_KEY, &app_state.history.serialize());
+    let _ = storage.set_item(HISTORY_CAP_KEY, &app_state.history.cap().to_string());
+    let _ = storage.set_item(BASIS_KEY, app_state.basis.name());
+    let _ = storage.set_item(BUDGET_KEY, &app_state.budget.to_string());
+    let _ = storage.set_item(POWERS_KEY, app_state.powers.name());
+}
+
+/// Loads what `save_settings` stored, falling back to the defaults.
+fn load_settings() -> (History, Basis, Budget, Associativity) {
+    let Some(storage) = local_storage() else {
+        return (
+            History::default(),
+            Basis::Standard,
+            Budget::default(),
+            Associativity::Right,
+        );
+    };
+    let read = |key: &str| storage.get_item(key).ok().flatten();
+    let cap = read(HISTORY_CAP_KEY)
+        .and_then(|cap| cap.parse().ok())
+        .unwrap_or(history::DEFAULT_CAP);
+    let history = read(HISTORY_KEY)
+        .map(|s| History::deserialize(&s, cap))
+        .unwrap_or_else(|| History::new(cap));
+    let basis = read(BASIS_KEY)
+        .and_then(|name| Basis::by_name(&name))
+        .unwrap_or(Basis::Standard);
+    let budget = read(BUDGET_KEY)
+        .and_then(|budget| budget.parse().ok())
+        .unwrap_or_default();
+    let powers = read(POWERS_KEY)
+        .and_then(|name| Associativity::by_name(&name))
+        .unwrap_or(Associativity::Right);
+    (history, basis, budget, powers)
+}
+
+// --- Limits ---
+/// The inputs in the limits panel, one per field of `Budget`.
+struct LimitInputs {
+    degree: HtmlInputElement,
+    coeff_bits: HtmlInputElement,
+    factorial: HtmlInputElement,
+    depth: HtmlInputElement,
+}
+
+impl LimitInputs {
+    fn all(&self) -> [&HtmlInputElement; 4] {
+        [&self.degree, &self.coeff_bits, &self.factorial, &self.depth]
+    }
+
+    fn show(&self, budget: &Budget) {
+        self.degree.set_value(&budget.max_degree.to_string());
+        self.coeff_bits
+            .set_value(&budget.max_coeff_bits.to_string());
+        self.factorial.set_value(&budget.max_factorial.to_string());
+        self.depth.set_value(&budget.max_depth.to_string());
+    }
+
+    /// The limits entered, keeping those of `current` where the input isn't a number.
+    fn read(&self, current: Budget) -> Budget {
+        let read = |input: &HtmlInputElement, old: u64| input.value().parse().unwrap_or(old);
+        Budget {
+            max_degree: read(&self.degree, current.max_degree),
+            max_coeff_bits: read(&self.coeff_bits, current.max_coeff_bits),
+            max_factorial: read(&self.factorial, current.max_factorial),
+            max_depth: read(&self.depth, current.max_depth),
+        }
+    }
+}
+
+fn input_by_id(document: &Document, id: &str) -> HtmlInputElement {
+    document
+        .get_element_by_id(id)
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap()
+}
+
+// --- Permalinks ---
+/// Records the current inputs in the URL fragment, adding a browser history entry
+/// if they changed.
+fn update_permalink(app_state: &AppState) {
+    let window = web_sys::window().unwrap();
+    let document = window.document().unwrap();
+    let expression = document
+        .get_element_by_id("expression-input")
+        .unwrap()
+        .dyn_into::<HtmlTextAreaElement>()
+        .unwrap()
+        .value();
+    let eval_input = document
+        .get_element_by_id("eval-input")
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap()
+        .value();
+    let session = Session {
+        expression,
+        basis: app_state.basis,
+        eval_input,
+    };
+    let fragment = format!("#{}", session.to_fragment());
+    if window.location().hash().ok().as_deref() == Some(fragment.as_str()) {
+        return;
+    }
+    window
+        .history()
+        .unwrap()
+        .push_state_with_url(&JsValue::NULL, "", Some(&fragment))
+        .unwrap();
+}
+
+/// Restores the session in the URL fragment, if there is one, and recomputes it.
+/// With `record` false the restore leaves the history and the URL alone.
+fn load_permalink(
+    app_state: &mut AppState,
+    input_element: &HtmlTextAreaElement,
+    result_output: &Element,
+    history_list_element: &Element,
+    eval_input: &HtmlInputElement,
+    eval_result: &Element,
+    record: bool,
+) {
+    let window = web_sys::window().unwrap();
+    let hash = window.location().hash().unwrap_or_default();
+    let Some(session) = Session::from_fragment(&hash) else {
+        return;
+    };
+    let basis_selector = window
+        .document()
+        .unwrap()
+        .get_element_by_id("basis-selector")
+        .unwrap()
+        .dyn_into::<HtmlSelectElement>()
+        .unwrap();
+    basis_selector.set_value(session.basis.name());
+    app_state.basis = session.basis;
+    input_element.set_value(&session.expression);
+    eval_input.set_value(&session.eval_input);
+
+    perform_calculation(
+        input_element,
+        result_output,
+        app_state,
+        history_list_element,
+        record,
+    );
+    perform_evaluation(eval_input, eval_result, app_state, record);
+}
+
+fn read_coeff_style(
+    coeff_style_selector: &HtmlSelectElement,
+    precision_input: &HtmlInputElement,
+) -> CoeffStyle {
+    match coeff_style_selector.value().as_str() {
+        "mixed" => CoeffStyle::Mixed,
+        "decimal" => CoeffStyle::Decimal,
+        "approximate" => CoeffStyle::Approximate(precision_input.value().parse().unwrap_or(6)),
+        _ => CoeffStyle::Fraction,
+    }
+}
+
+fn change_plot_view(button_id: &str, view: Viewport) -> Viewport {
+    match button_id {
+        "plot-zoom-in" => view.zoom(0.5),
+        "plot-zoom-out" => view.zoom(2.0),
+        "plot-pan-left" => view.pan(-0.25),
+        "plot-pan-right" => view.pan(0.25),
+        _ => Viewport::default(),
     }
 }
 
@@ -123,7 +840,10 @@ fn perform_evaluation(eval_input: &HtmlInputElement, eval_result: &Element, app_
 pub fn start() {
     console_error_panic_hook::set_once();
 
-    let window = web_sys::window().unwrap();
+    // The worker runs this module too, without a page to set up
+    let Some(window) = web_sys::window() else {
+        return;
+    };
     let document = window.document().unwrap();
     let input_element = document
         .get_element_by_id("expression-input")
@@ -140,19 +860,119 @@ pub fn start() {
         .unwrap();
     let eval_button = document.get_element_by_id("eval-button").unwrap();
     let eval_result = document.get_element_by_id("eval-result").unwrap();
+    let pretty_toggle = document
+        .get_element_by_id("pretty-toggle")
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+    let coeff_style_selector = document
+        .get_element_by_id("coeff-style-selector")
+        .unwrap()
+        .dyn_into::<HtmlSelectElement>()
+        .unwrap();
+    let precision_input = document
+        .get_element_by_id("precision-input")
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+    let factor_toggle = document
+        .get_element_by_id("factor-toggle")
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+    let copy_button = document.get_element_by_id("copy-button").unwrap();
+    let copy_format_selector = document
+        .get_element_by_id("copy-format-selector")
+        .unwrap()
+        .dyn_into::<HtmlSelectElement>()
+        .unwrap();
     let basis_selector = document
         .get_element_by_id("basis-selector")
         .unwrap()
         .dyn_into::<HtmlSelectElement>()
         .unwrap();
+    let powers_selector = document
+        .get_element_by_id("powers-selector")
+        .unwrap()
+        .dyn_into::<HtmlSelectElement>()
+        .unwrap();
+    let work_toggle = document
+        .get_element_by_id("work-toggle")
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+
+    let history_filter_input = document
+        .get_element_by_id("history-filter")
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+    let history_cap_input = document
+        .get_element_by_id("history-cap")
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+
+    let limit_inputs = LimitInputs {
+        degree: input_by_id(&document, "limit-degree"),
+        coeff_bits: input_by_id(&document, "limit-coeff-bits"),
+        factorial: input_by_id(&document, "limit-factorial"),
+        depth: input_by_id(&document, "limit-depth"),
+    };
 
     // App State
+    let (history, basis, budget, powers) = load_settings();
+    basis_selector.set_value(basis.name());
+    powers_selector.set_value(powers.name());
+    history_cap_input.set_value(&history.cap().to_string());
+    limit_inputs.show(&budget);
     let app_state = Rc::new(RefCell::new(AppState {
-        history: Vec::new(),
+        history,
+        history_filter: String::new(),
         current_poly: None,
-        basis: Basis::Standard,
+        current_integrality: None,
+        basis,
+        formatter: if pretty_toggle.checked() {
+            &Unicode
+        } else {
+            &Plain
+        },
+        coeff_style: read_coeff_style(&coeff_style_selector, &precision_input),
+        factor_content: factor_toggle.checked(),
+        plot_view: Viewport::default(),
+        budget,
+        powers,
+        show_work: work_toggle.checked(),
+        eval_table: Vec::new(),
+        worker: None,
+        pending: None,
+        next_request_id: 0,
     }));
 
+    // Calculations run in a worker when possible
+    {
+        let state_clone = Rc::clone(&app_state);
+        app_state.borrow_mut().worker = CalcWorker::spawn(move |response| {
+            receive_calculation(&mut state_clone.borrow_mut(), response);
+        });
+    }
+
+    // Cancel button handler
+    {
+        let state_clone = Rc::clone(&app_state);
+        let result_clone = result_output.clone();
+
+        let on_cancel = Closure::<dyn FnMut()>::new(move || {
+            cancel_calculation(&mut state_clone.borrow_mut(), &result_clone);
+        });
+        document
+            .get_element_by_id("cancel-button")
+            .unwrap()
+            .add_event_listener_with_callback("click", on_cancel.as_ref().unchecked_ref())
+            .unwrap();
+        on_cancel.forget();
+    }
+
     // Basis Selector handler
     {
         let state_clone = Rc::clone(&app_state);
@@ -162,13 +982,10 @@ pub fn start() {
 
         let on_basis_change = Closure::<dyn FnMut()>::new(move || {
             let mut state = state_clone.borrow_mut();
-            let new_basis_str = basis_selector_clone.value();
-            state.basis = if new_basis_str == "binomial" {
-                Basis::Binomial
-            } else {
-                Basis::Standard
-            };
+            state.basis = Basis::by_name(&basis_selector_clone.value()).unwrap_or(Basis::Standard);
             rerender_result(&state, &result_clone, &history_list_clone);
+            save_settings(&state);
+            update_permalink(&state);
         });
 
         basis_selector
@@ -177,6 +994,258 @@ pub fn start() {
         on_basis_change.forget();
     }
 
+    // Pretty output toggle handler
+    {
+        let state_clone = Rc::clone(&app_state);
+        let result_clone = result_output.clone();
+        let history_list_clone = history_list_element.clone();
+        let pretty_toggle_clone = pretty_toggle.clone();
+
+        let on_toggle = Closure::<dyn FnMut()>::new(move || {
+            let mut state = state_clone.borrow_mut();
+            state.formatter = if pretty_toggle_clone.checked() {
+                &Unicode
+            } else {
+                &Plain
+            };
+            rerender_result(&state, &result_clone, &history_list_clone);
+        });
+
+        pretty_toggle
+            .add_event_listener_with_callback("change", on_toggle.as_ref().unchecked_ref())
+            .unwrap();
+        on_toggle.forget();
+    }
+
+    // Coefficient style handler
+    {
+        let state_clone = Rc::clone(&app_state);
+        let result_clone = result_output.clone();
+        let history_list_clone = history_list_element.clone();
+        let coeff_style_selector_clone = coeff_style_selector.clone();
+        let precision_input_clone = precision_input.clone();
+
+        let on_style_change = Closure::<dyn FnMut()>::new(move || {
+            let mut state = state_clone.borrow_mut();
+            state.coeff_style =
+                read_coeff_style(&coeff_style_selector_clone, &precision_input_clone);
+            rerender_result(&state, &result_clone, &history_list_clone);
+        });
+
+        for element in [
+            coeff_style_selector.unchecked_ref::<Element>(),
+            &precision_input,
+        ] {
+            element
+                .add_event_listener_with_callback(
+                    "change",
+                    on_style_change.as_ref().unchecked_ref(),
+                )
+                .unwrap();
+        }
+        on_style_change.forget();
+    }
+
+    // Common denominator toggle handler
+    {
+        let state_clone = Rc::clone(&app_state);
+        let result_clone = result_output.clone();
+        let history_list_clone = history_list_element.clone();
+        let factor_toggle_clone = factor_toggle.clone();
+
+        let on_toggle = Closure::<dyn FnMut()>::new(move || {
+            let mut state = state_clone.borrow_mut();
+            state.factor_content = factor_toggle_clone.checked();
+            rerender_result(&state, &result_clone, &history_list_clone);
+        });
+
+        factor_toggle
+            .add_event_listener_with_callback("change", on_toggle.as_ref().unchecked_ref())
+            .unwrap();
+        on_toggle.forget();
+    }
+
+    // Plot zoom and pan handlers
+    {
+        let plot_buttons = [
+            "plot-zoom-in",
+            "plot-zoom-out",
+            "plot-pan-left",
+            "plot-pan-right",
+            "plot-reset",
+        ];
+        for id in plot_buttons {
+            let state_clone = Rc::clone(&app_state);
+
+            let on_click = Closure::<dyn FnMut()>::new(move || {
+                let mut state = state_clone.borrow_mut();
+                state.plot_view = change_plot_view(id, state.plot_view);
+                render_plot(&state);
+            });
+            document
+                .get_element_by_id(id)
+                .unwrap()
+                .add_event_listener_with_callback("click", on_click.as_ref().unchecked_ref())
+                .unwrap();
+            on_click.forget();
+        }
+    }
+
+    // History list handler: pin buttons and loading queries
+    {
+        let state_clone = Rc::clone(&app_state);
+        let input_clone = input_element.clone();
+        let history_list_clone = history_list_element.clone();
+
+        let on_click = Closure::<dyn FnMut(_)>::new(move |event: Event| {
+            let Some(target) = event.target().and_then(|t| t.dyn_into::<Element>().ok()) else {
+                return;
+            };
+            let Some(id) = target
+                .closest(".history-entry")
+                .ok()
+                .flatten()
+                .and_then(|entry| entry.get_attribute("data-id"))
+                .and_then(|id| id.parse::<u64>().ok())
+            else {
+                return;
+            };
+            let mut state = state_clone.borrow_mut();
+            let Some(index) = state.history.index_of(id) else {
+                return;
+            };
+            if target.closest(".history-pin").ok().flatten().is_some() {
+                state.history.toggle_pin(index);
+                save_settings(&state);
+                update_history_display(&state, &history_list_clone);
+            } else if let Some(entry) = state.history.entries().get(index) {
+                input_clone.set_value(&entry.query);
+                input_clone.focus().unwrap();
+            }
+        });
+        history_list_element
+            .add_event_listener_with_callback("click", on_click.as_ref().unchecked_ref())
+            .unwrap();
+        on_click.forget();
+    }
+
+    // History filter handler
+    {
+        let state_clone = Rc::clone(&app_state);
+        let history_list_clone = history_list_element.clone();
+        let history_filter_clone = history_filter_input.clone();
+
+        let on_input = Closure::<dyn FnMut()>::new(move || {
+            let mut state = state_clone.borrow_mut();
+            state.history_filter = history_filter_clone.value();
+            update_history_display(&state, &history_list_clone);
+        });
+        history_filter_input
+            .add_event_listener_with_callback("input", on_input.as_ref().unchecked_ref())
+            .unwrap();
+        on_input.forget();
+    }
+
+    // History cap handler
+    {
+        let state_clone = Rc::clone(&app_state);
+        let history_list_clone = history_list_element.clone();
+        let history_cap_clone = history_cap_input.clone();
+
+        let on_change = Closure::<dyn FnMut()>::new(move || {
+            let mut state = state_clone.borrow_mut();
+            if let Ok(cap) = history_cap_clone.value().parse() {
+                state.history.set_cap(cap);
+            }
+            history_cap_clone.set_value(&state.history.cap().to_string());
+            save_settings(&state);
+            update_history_display(&state, &history_list_clone);
+        });
+        history_cap_input
+            .add_event_listener_with_callback("change", on_change.as_ref().unchecked_ref())
+            .unwrap();
+        on_change.forget();
+    }
+
+    // Limits handler
+    {
+        let state_clone = Rc::clone(&app_state);
+        let limit_inputs = Rc::new(limit_inputs);
+
+        let limit_inputs_clone = Rc::clone(&limit_inputs);
+        let on_change = Closure::<dyn FnMut()>::new(move || {
+            let mut state = state_clone.borrow_mut();
+            state.budget = limit_inputs_clone.read(state.budget);
+            limit_inputs_clone.show(&state.budget);
+            save_settings(&state);
+        });
+        for input in limit_inputs.all() {
+            input
+                .add_event_listener_with_callback("change", on_change.as_ref().unchecked_ref())
+                .unwrap();
+        }
+        on_change.forget();
+    }
+
+    // Associativity selector handler: applies from the next calculation
+    {
+        let state_clone = Rc::clone(&app_state);
+        let powers_selector_clone = powers_selector.clone();
+
+        let on_change = Closure::<dyn FnMut()>::new(move || {
+            let mut state = state_clone.borrow_mut();
+            state.powers =
+                Associativity::by_name(&powers_selector_clone.value()).unwrap_or(state.powers);
+            save_settings(&state);
+        });
+        powers_selector
+            .add_event_listener_with_callback("change", on_change.as_ref().unchecked_ref())
+            .unwrap();
+        on_change.forget();
+    }
+
+    // Show work toggle handler: applies from the next calculation
+    {
+        let state_clone = Rc::clone(&app_state);
+        let work_toggle_clone = work_toggle.clone();
+
+        let on_toggle = Closure::<dyn FnMut()>::new(move || {
+            state_clone.borrow_mut().show_work = work_toggle_clone.checked();
+        });
+        work_toggle
+            .add_event_listener_with_callback("change", on_toggle.as_ref().unchecked_ref())
+            .unwrap();
+        on_toggle.forget();
+    }
+
+    // Copy button handler
+    {
+        let state_clone = Rc::clone(&app_state);
+        let copy_format_selector_clone = copy_format_selector.clone();
+
+        let on_copy = Closure::<dyn FnMut()>::new(move || {
+            copy_result(&copy_format_selector_clone, &state_clone.borrow());
+        });
+        copy_button
+            .add_event_listener_with_callback("click", on_copy.as_ref().unchecked_ref())
+            .unwrap();
+        on_copy.forget();
+    }
+
+    // Value table copy handlers
+    for (id, separator) in [("copy-table-csv", ','), ("copy-table-tsv", '\t')] {
+        let state_clone = Rc::clone(&app_state);
+        let on_copy = Closure::<dyn FnMut()>::new(move || {
+            copy_table(&state_clone.borrow(), separator);
+        });
+        document
+            .get_element_by_id(id)
+            .unwrap()
+            .add_event_listener_with_callback("click", on_copy.as_ref().unchecked_ref())
+            .unwrap();
+        on_copy.forget();
+    }
+
     // Main calculation handler
     {
         let state_clone = Rc::clone(&app_state);
@@ -190,6 +1259,7 @@ pub fn start() {
                 &result_clone,
                 &mut state_clone.borrow_mut(),
                 &history_list_clone,
+                true,
             );
         });
         calculate_button
@@ -213,6 +1283,7 @@ pub fn start() {
                     &result_clone,
                     &mut state_clone.borrow_mut(),
                     &history_list_clone,
+                    true,
                 );
             }
         });
@@ -229,7 +1300,12 @@ pub fn start() {
         let eval_result_clone = eval_result.clone();
 
         let on_eval = Closure::<dyn FnMut()>::new(move || {
-            perform_evaluation(&eval_input_clone, &eval_result_clone, &state_clone.borrow());
+            perform_evaluation(
+                &eval_input_clone,
+                &eval_result_clone,
+                &mut state_clone.borrow_mut(),
+                true,
+            );
         });
         eval_button
             .add_event_listener_with_callback("click", on_eval.as_ref().unchecked_ref())
@@ -246,7 +1322,12 @@ pub fn start() {
         let on_keydown = Closure::<dyn FnMut(_)>::new(move |event: KeyboardEvent| {
             if event.key() == "Enter" {
                 event.prevent_default();
-                perform_evaluation(&eval_input_clone, &eval_result_clone, &state_clone.borrow());
+                perform_evaluation(
+                    &eval_input_clone,
+                    &eval_result_clone,
+                    &mut state_clone.borrow_mut(),
+                    true,
+                );
             }
         });
         eval_input
@@ -254,4 +1335,119 @@ pub fn start() {
             .unwrap();
         on_keydown.forget();
     }
+
+    update_history_display(&app_state.borrow(), &history_list_element);
+
+    // Permalink handlers: restore on load and on back/forward navigation
+    {
+        let state_clone = Rc::clone(&app_state);
+        let input_clone = input_element.clone();
+        let result_clone = result_output.clone();
+        let history_list_clone = history_list_element.clone();
+        let eval_input_clone = eval_input.clone();
+        let eval_result_clone = eval_result.clone();
+
+        let on_popstate = Closure::<dyn FnMut()>::new(move || {
+            load_permalink(
+                &mut state_clone.borrow_mut(),
+                &input_clone,
+                &result_clone,
+                &history_list_clone,
+                &eval_input_clone,
+                &eval_result_clone,
+                false,
+            );
+        });
+        window
+            .add_event_listener_with_callback("popstate", on_popstate.as_ref().unchecked_ref())
+            .unwrap();
+        on_popstate.forget();
+
+        load_permalink(
+            &mut app_state.borrow_mut(),
+            &input_element,
+            &result_output,
+            &history_list_element,
+            &eval_input,
+            &eval_result,
+            true,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::delimited_table;
+    use crate::budget::Budget;
+    use crate::parse::{parse, parse_eval_input, Associativity, Bindings, Parsed};
+    use crate::polynomial::x;
+
+    const PAGE: &str = include_str!("../index.html");
+
+    /// The examples in the placeholder of the element with `id`, split at the
+    /// commas and the `or` between them rather than those within brackets.
+    fn placeholder_examples(id: &str) -> Vec<String> {
+        let (_, tag) = PAGE.split_once(&format!("id=\"{}\"", id)).unwrap();
+        let (_, placeholder) = tag.split_once("placeholder=\"").unwrap();
+        let (placeholder, _) = placeholder.split_once('"').unwrap();
+        let (_, list) = placeholder.split_once("e.g., ").unwrap();
+        let mut examples = vec![String::new()];
+        let mut depth = 0;
+        for part in list.replace(" or ", ", ").split(", ") {
+            let current = examples.last_mut().unwrap();
+            if !current.is_empty() {
+                current.push_str(", ");
+            }
+            current.push_str(part);
+            depth += part.matches(['(', '[']).count() as i32;
+            depth -= part.matches([')', ']']).count() as i32;
+            if depth == 0 {
+                examples.push(String::new());
+            }
+        }
+        examples.pop();
+        examples
+    }
+
+    #[test]
+    fn placeholder_examples_parse() {
+        let previous = Parsed::Rational(x(()));
+        let bindings = Bindings::new(vec![Some(&previous)]);
+        let budget = Budget::default();
+        let queries = placeholder_examples("expression-input");
+        assert_eq!(queries.len(), 4);
+        for query in queries {
+            let parsed = parse(&query, &bindings, &budget, Associativity::Right);
+            assert!(parsed.is_ok(), "{}: {:?}", query, parsed);
+        }
+        let points = placeholder_examples("eval-input");
+        assert_eq!(points.len(), 5);
+        for input in points {
+            let parsed = parse_eval_input(&input, &bindings, &budget, Associativity::Right);
+            assert!(parsed.is_ok(), "{}: {:?}", input, parsed.err());
+        }
+    }
+
+    #[test]
+    fn value_tables_copy_as_csv_and_tsv() {
+        let rows = |cells: &[[&str; 2]]| -> Vec<[String; 2]> {
+            cells.iter().map(|row| row.map(str::to_string)).collect()
+        };
+        let table = rows(&[["-1/2", "3/4"], ["2", "1.(3)"]]);
+        assert_eq!(delimited_table(&table, ','), "x,p(x)\n-1/2,3/4\n2,1.(3)\n");
+        assert_eq!(
+            delimited_table(&table, '\t'),
+            "x\tp(x)\n-1/2\t3/4\n2\t1.(3)\n"
+        );
+        let awkward = rows(&[["1,5", "say \"hi\""], ["a\tb", "c\nd"]]);
+        assert_eq!(
+            delimited_table(&awkward, ','),
+            "x,p(x)\n\"1,5\",\"say \"\"hi\"\"\"\na\tb,\"c\nd\"\n"
+        );
+        assert_eq!(
+            delimited_table(&awkward, '\t'),
+            "x\tp(x)\n1,5\tsay \"hi\"\na b\tc d\n"
+        );
+        assert_eq!(delimited_table(&[], ','), "x,p(x)\n");
+    }
 }
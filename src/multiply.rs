@@ -0,0 +1,373 @@
+use std::ops::{AddAssign, MulAssign, SubAssign};
+
+use num_bigint::{BigInt, BigUint, Sign};
+use num_traits::{One, Zero};
+
+use crate::budget::bit_length;
+use crate::interrupt;
+
+// --- Coefficient List Products ---
+// Products of coefficient lists, constant term first. Polynomial multiplication
+// picks the algorithm by size: schoolbook for short factors, Karatsuba for any
+// ring, and Kronecker substitution for integers, which turns the whole product
+// into one big-integer multiplication (and so into num-bigint's Toom-3).
+
+/// Below this length, schoolbook multiplication beats Karatsuba's bookkeeping.
+const KARATSUBA_THRESHOLD: usize = 16;
+/// From this length on, integer products go through Kronecker substitution.
+const KRONECKER_THRESHOLD: usize = 32;
+
+/// What the multiplication algorithms need from the coefficients.
+pub(crate) trait Ring:
+    Clone
+    + Zero
+    + for<'a> AddAssign<&'a Self>
+    + for<'a> SubAssign<&'a Self>
+    + for<'a> MulAssign<&'a Self>
+{
+}
+
+impl<T> Ring for T where
+    T: Clone
+        + Zero
+        + for<'a> AddAssign<&'a T>
+        + for<'a> SubAssign<&'a T>
+        + for<'a> MulAssign<&'a T>
+{
+}
+
+fn schoolbook<T: Ring>(a: &[T], b: &[T]) -> Vec<T> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut result = vec![T::zero(); a.len() + b.len() - 1];
+    for (i, c1) in a.iter().enumerate() {
+        if interrupt::checkpoint() {
+            break;
+        }
+        for (j, c2) in b.iter().enumerate() {
+            let mut product = c1.clone();
+            product *= c2;
+            result[i + j] += &product;
+        }
+    }
+    result
+}
+
+/// Multiplies by splitting each factor in halves, taking three half-size products
+/// instead of four.
+pub(crate) fn karatsuba<T: Ring>(a: &[T], b: &[T]) -> Vec<T> {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    if short.len() < KARATSUBA_THRESHOLD {
+        return schoolbook(a, b);
+    }
+    let mut result = vec![T::zero(); a.len() + b.len() - 1];
+    if interrupt::checkpoint() {
+        return result;
+    }
+    if 2 * short.len() <= long.len() {
+        // Too unbalanced to split evenly: multiply by `short` one slice at a time.
+        for (k, chunk) in long.chunks(short.len()).enumerate() {
+            add_at(&mut result, k * short.len(), &karatsuba(short, chunk));
+        }
+        return result;
+    }
+
+    let m = long.len() / 2;
+    let (a0, a1) = a.split_at(m);
+    let (b0, b1) = b.split_at(m);
+    let low = karatsuba(a0, b0);
+    let high = karatsuba(a1, b1);
+    let mut middle = karatsuba(&sum(a0, a1), &sum(b0, b1));
+    for (i, c) in low.iter().enumerate() {
+        middle[i] -= c;
+    }
+    for (i, c) in high.iter().enumerate() {
+        middle[i] -= c;
+    }
+    add_at(&mut result, 0, &low);
+    add_at(&mut result, m, &middle);
+    add_at(&mut result, 2 * m, &high);
+    result
+}
+
+fn sum<T: Ring>(a: &[T], b: &[T]) -> Vec<T> {
+    let mut result = a.to_vec();
+    result.resize(a.len().max(b.len()), T::zero());
+    for (r, c) in result.iter_mut().zip(b) {
+        *r += c;
+    }
+    result
+}
+
+/// Adds `terms` to `result`, starting at index `offset`. Trailing zeros that don't
+/// fit are dropped; they can appear when an intermediate sum is zero-padded.
+fn add_at<T: Ring>(result: &mut [T], offset: usize, terms: &[T]) {
+    for (r, c) in result[offset..].iter_mut().zip(terms) {
+        *r += c;
+    }
+}
+
+/// The product of two integer coefficient lists, by whichever method suits their size.
+pub(crate) fn integer_product(a: &[BigInt], b: &[BigInt]) -> Vec<BigInt> {
+    if a.len().min(b.len()) < KRONECKER_THRESHOLD {
+        karatsuba(a, b)
+    } else {
+        kronecker(a, b)
+    }
+}
+
+// --- Kronecker Substitution ---
+/// Evaluates both factors at `x = 2^k`, with `k` large enough that no coefficient of
+/// the product overflows its slot, multiplies the two integers and reads the
+/// product's coefficients back off as signed base-`2^k` digits.
+fn kronecker(a: &[BigInt], b: &[BigInt]) -> Vec<BigInt> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let max_bits = |p: &[BigInt]| p.iter().map(BigInt::bits).max().unwrap_or(0);
+    let terms = a.len().min(b.len()) as u64;
+    // Every product coefficient is below 2^(slot - 1) in absolute value.
+    let slot = max_bits(a) + max_bits(b) + bit_length(terms) + 1;
+    let product = pack(a, slot) * pack(b, slot);
+    unpack(&product, slot, a.len() + b.len() - 1)
+}
+
+/// `sum(p[i] * 2^(slot * i))`, built limb by limb so it takes linear time.
+fn pack(p: &[BigInt], slot: u64) -> BigInt {
+    let limbs = (slot * p.len() as u64).div_ceil(64) as usize + 1;
+    let mut positive = vec![0u64; limbs];
+    let mut negative = vec![0u64; limbs];
+    for (i, c) in p.iter().enumerate() {
+        let target = match c.sign() {
+            Sign::Minus => &mut negative,
+            _ => &mut positive,
+        };
+        write_bits(target, slot * i as u64, &c.magnitude().to_u64_digits());
+    }
+    BigInt::from(BigUint::new(to_u32_digits(&positive)))
+        - BigInt::from(BigUint::new(to_u32_digits(&negative)))
+}
+
+/// ORs `value` into `limbs` starting at bit `offset`.
+fn write_bits(limbs: &mut [u64], offset: u64, value: &[u64]) {
+    let (start, shift) = ((offset / 64) as usize, offset % 64);
+    for (i, &v) in value.iter().enumerate() {
+        limbs[start + i] |= v << shift;
+        if shift > 0 {
+            limbs[start + i + 1] |= v >> (64 - shift);
+        }
+    }
+}
+
+/// The `len` bits of `limbs` starting at bit `offset`.
+fn read_bits(limbs: &[u64], offset: u64, len: u64) -> BigUint {
+    let (start, shift) = ((offset / 64) as usize, offset % 64);
+    let count = len.div_ceil(64) as usize;
+    let limb = |i: usize| limbs.get(i).copied().unwrap_or(0);
+    let mut value: Vec<u64> = (0..count)
+        .map(|i| {
+            let low = limb(start + i) >> shift;
+            let high = if shift > 0 {
+                limb(start + i + 1) << (64 - shift)
+            } else {
+                0
+            };
+            low | high
+        })
+        .collect();
+    if !len.is_multiple_of(64) {
+        if let Some(last) = value.last_mut() {
+            *last &= (1 << (len % 64)) - 1;
+        }
+    }
+    BigUint::new(to_u32_digits(&value))
+}
+
+/// Reads `count` balanced digits in base `2^slot`, each in `[-2^(slot-1), 2^(slot-1))`.
+fn unpack(value: &BigInt, slot: u64, count: usize) -> Vec<BigInt> {
+    let limbs = value.magnitude().to_u64_digits();
+    let half = BigUint::one() << (slot - 1);
+    let full = BigInt::one() << slot;
+    let mut carry = false;
+    let mut digits = Vec::with_capacity(count);
+    for i in 0..count {
+        let mut chunk = read_bits(&limbs, slot * i as u64, slot);
+        if carry {
+            chunk += 1u32;
+        }
+        carry = chunk >= half;
+        let mut digit = BigInt::from(chunk);
+        if carry {
+            digit -= &full;
+        }
+        digits.push(if value.sign() == Sign::Minus {
+            -digit
+        } else {
+            digit
+        });
+    }
+    digits
+}
+
+fn to_u32_digits(limbs: &[u64]) -> Vec<u32> {
+    limbs
+        .iter()
+        .flat_map(|&limb| [limb as u32, (limb >> 32) as u32])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+    use num_traits::{One, Signed, Zero};
+
+    use super::{integer_product, karatsuba, schoolbook, KRONECKER_THRESHOLD};
+
+    /// A xorshift generator, so failures reproduce.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        /// A signed integer of up to `max_bits` bits.
+        fn int(&mut self, max_bits: u64) -> BigInt {
+            let bits = self.next() % (max_bits + 1);
+            let mut value = BigInt::zero();
+            for _ in 0..bits.div_ceil(64) {
+                value = (value << 64) + self.next();
+            }
+            value >>= bits.div_ceil(64) * 64 - bits;
+            if self.next() & 1 == 0 {
+                -value
+            } else {
+                value
+            }
+        }
+
+        fn list(&mut self, len: usize, max_bits: u64) -> Vec<BigInt> {
+            (0..len).map(|_| self.int(max_bits)).collect()
+        }
+    }
+
+    fn check(a: &[BigInt], b: &[BigInt]) {
+        let expected = schoolbook(a, b);
+        assert_eq!(karatsuba(a, b), expected, "karatsuba {:?} * {:?}", a, b);
+        assert_eq!(
+            integer_product(a, b),
+            expected,
+            "kronecker {:?} * {:?}",
+            a,
+            b
+        );
+    }
+
+    fn ints(values: &[i64]) -> Vec<BigInt> {
+        values.iter().map(|&v| BigInt::from(v)).collect()
+    }
+
+    #[test]
+    fn random_products_match_schoolbook() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..200 {
+            let (m, n) = (rng.next() % 100 + 1, rng.next() % 100 + 1);
+            let bits = rng.next() % 300;
+            let a = rng.list(m as usize, bits);
+            let b = rng.list(n as usize, bits);
+            check(&a, &b);
+        }
+    }
+
+    #[test]
+    fn negative_products_match_schoolbook() {
+        let mut rng = Rng(17);
+        let negative =
+            |p: Vec<BigInt>| -> Vec<BigInt> { p.into_iter().map(|c| -c.abs() - 1).collect() };
+        let a = negative(rng.list(70, 90));
+        let b = negative(rng.list(45, 10));
+        check(&a, &b);
+        check(&a, &a);
+        // Digits that sit exactly on the balanced range's edges
+        let edges = ints(&[i64::MIN, i64::MAX, -1, 1, i64::MIN + 1, 0].repeat(12));
+        check(&edges, &edges);
+        check(&edges, &ints(&[-1; 40]));
+    }
+
+    #[test]
+    fn unbalanced_products_match_schoolbook() {
+        let mut rng = Rng(5);
+        for (m, n) in [
+            (1, 300),
+            (16, 500),
+            (33, 1000),
+            (40, 81),
+            (64, 129),
+            (2, 33),
+        ] {
+            let a = rng.list(m, 64);
+            let b = rng.list(n, 200);
+            check(&a, &b);
+            check(&b, &a);
+        }
+    }
+
+    #[test]
+    fn zero_padded_products_match_schoolbook() {
+        let mut rng = Rng(99);
+        let mut a = vec![BigInt::zero(); 20];
+        a.extend(rng.list(30, 64));
+        a.extend(vec![BigInt::zero(); 25]);
+        let b = rng.list(50, 128);
+        check(&a, &b);
+        check(&a, &vec![BigInt::zero(); 40]);
+        check(&vec![BigInt::zero(); 40], &vec![BigInt::zero(); 40]);
+        assert!(integer_product(&a, &[]).is_empty());
+    }
+
+    #[test]
+    fn products_above_both_thresholds_match_schoolbook() {
+        let mut rng = Rng(2024);
+        let n = 4 * KRONECKER_THRESHOLD + 3;
+        let a = rng.list(n, 1000);
+        let b = rng.list(n + 1, 3);
+        check(&a, &b);
+        let ones = vec![BigInt::one(); n];
+        let expected: Vec<BigInt> = (1..=n).chain((1..n).rev()).map(BigInt::from).collect();
+        assert_eq!(integer_product(&ones, &ones), expected);
+    }
+
+    #[test]
+    fn largest_coefficients_fit_their_slots() {
+        for n in [32, 33, 63, 64, 65, 128] {
+            for bits in [1, 63, 64, 65] {
+                let max: BigInt = (BigInt::one() << bits) - 1;
+                let a = vec![max.clone(); n];
+                let alternating: Vec<_> = (0..n)
+                    .map(|i| if i % 2 == 0 { max.clone() } else { -&max })
+                    .collect();
+                check(&a, &a);
+                check(&alternating, &alternating);
+                check(&a, &vec![-BigInt::one(); n]);
+            }
+        }
+    }
+
+    #[test]
+    fn karatsuba_works_over_the_rationals() {
+        let mut rng = Rng(3);
+        let list = |rng: &mut Rng, n| -> Vec<BigRational> {
+            (0..n)
+                .map(|_| BigRational::new(rng.int(40), rng.int(20).abs() + 1))
+                .collect()
+        };
+        let a = list(&mut rng, 37);
+        let b = list(&mut rng, 20);
+        assert_eq!(karatsuba(&a, &b), schoolbook(&a, &b));
+    }
+}
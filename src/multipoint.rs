@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Zero};
+
+use crate::interrupt;
+use crate::polynomial::Polynomial;
+
+// --- Evaluation at Many Points ---
+impl Polynomial {
+    /// Evaluates at every point in `xs`. Points are grouped by denominator: for
+    /// the points `p/q` of one group, the numerators homogenized by `q` form one
+    /// integer polynomial to evaluate at the integers `p`, so the inner loops never
+    /// normalize a fraction. Sparse polynomials go through `eval` instead, which
+    /// skips the missing terms.
+    pub(crate) fn eval_each(&self, xs: &[BigRational]) -> Vec<BigRational> {
+        if self.is_sparse() {
+            return xs.iter().map(|x| self.eval(x)).collect();
+        }
+        let numers = self.numers();
+        let mut values = vec![BigRational::zero(); xs.len()];
+        if numers.is_empty() {
+            return values;
+        }
+        let mut groups: BTreeMap<&BigInt, Vec<usize>> = BTreeMap::new();
+        for (i, x) in xs.iter().enumerate() {
+            groups.entry(x.denom()).or_default().push(i);
+        }
+        for (q, indices) in groups {
+            // c_k * q^(n - k), so that the value at p is q^n times that at p/q
+            let mut homogenized = numers.to_vec();
+            let mut q_pow = BigInt::one();
+            for c in homogenized.iter_mut().rev() {
+                *c *= &q_pow;
+                q_pow *= q;
+            }
+            let scale = q_pow / q * self.denom();
+            let ps: Vec<BigInt> = indices.iter().map(|&i| xs[i].numer().clone()).collect();
+            for (i, value) in indices.into_iter().zip(eval_integers(&homogenized, &ps)) {
+                values[i] = BigRational::new(value, scale.clone());
+            }
+        }
+        values
+    }
+
+    /// Evaluates at `start, start + step, ..., start + (count - 1) * step`. Only the
+    /// first `degree + 1` values are computed directly; the rest are built from a
+    /// forward-difference table using additions alone.
+    pub(crate) fn eval_progression(
+        &self,
+        start: &BigRational,
+        step: &BigRational,
+        count: usize,
+    ) -> Vec<BigRational> {
+        let direct_count = count.min(self.degree() + 1);
+        let direct_points: Vec<BigRational> = (0..direct_count)
+            .map(|i| start + step * BigRational::from_integer(i.into()))
+            .collect();
+        let mut values = self.eval_each(&direct_points);
+        if count <= values.len() {
+            return values;
+        }
+
+        // diffs[k] holds the k-th forward difference at the current point.
+        let mut diffs = Vec::with_capacity(values.len());
+        let mut row = values.clone();
+        while let Some(first) = row.first() {
+            diffs.push(first.clone());
+            row = row.windows(2).map(|w| &w[1] - &w[0]).collect();
+        }
+        for _ in 0..values.len() - 1 {
+            advance(&mut diffs);
+        }
+        while values.len() < count {
+            advance(&mut diffs);
+            values.push(diffs[0].clone());
+        }
+        values
+    }
+}
+
+/// Steps a forward-difference table one point ahead.
+fn advance(diffs: &mut [BigRational]) {
+    for k in 0..diffs.len() - 1 {
+        let next = diffs[k + 1].clone();
+        diffs[k] += next;
+    }
+}
+
+// --- Divide and Conquer ---
+// Horner's rule multiplies an accumulator that grows to the size of the value by a
+// small point at every step, which is quadratic in the degree. Splitting
+// `p = low + y^h * high` instead puts the work into a few large, balanced products,
+// where num-bigint's Karatsuba and Toom-3 pay off. (A subproduct tree would share
+// work between points, but its remainders are so much larger than the values that
+// without FFT multiplication it loses to Horner.)
+
+/// Below this many coefficients, Horner's rule is faster.
+const SPLIT_THRESHOLD: usize = 32;
+
+/// The integer polynomial with coefficients `coeffs`, constant term first, at
+/// every integer in `ys`.
+pub(crate) fn eval_integers(coeffs: &[BigInt], ys: &[BigInt]) -> Vec<BigInt> {
+    let mut values = Vec::with_capacity(ys.len());
+    for y in ys {
+        if interrupt::checkpoint() {
+            break;
+        }
+        // y^(2^k) for each split point 2^k below the length
+        let mut powers = vec![y.clone()];
+        while 1 << powers.len() < coeffs.len() {
+            let square = &powers[powers.len() - 1] * &powers[powers.len() - 1];
+            powers.push(square);
+        }
+        values.push(eval_split(coeffs, &powers));
+    }
+    values.resize(ys.len(), BigInt::zero());
+    values
+}
+
+fn eval_split(coeffs: &[BigInt], powers: &[BigInt]) -> BigInt {
+    if coeffs.len() <= SPLIT_THRESHOLD {
+        return horner(coeffs, &powers[0]);
+    }
+    let k = (coeffs.len() - 1).ilog2() as usize;
+    let (low, high) = coeffs.split_at(1 << k);
+    eval_split(low, powers) + &powers[k] * eval_split(high, powers)
+}
+
+fn horner(coeffs: &[BigInt], y: &BigInt) -> BigInt {
+    coeffs
+        .iter()
+        .rev()
+        .fold(BigInt::zero(), |acc, c| acc * y + c)
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+
+    use super::{eval_integers, horner, SPLIT_THRESHOLD};
+    use crate::polynomial::Polynomial;
+
+    /// Deterministic integers in `-bound..bound`.
+    fn ints(seed: u64, count: usize, bound: i64) -> Vec<BigInt> {
+        let mut state = seed;
+        (0..count)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                BigInt::from((state >> 33) as i64 % bound)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splitting_agrees_with_horner() {
+        // Around the threshold, and around powers of two where the split moves
+        for (len, points) in [
+            (0, 3),
+            (1, 3),
+            (32, 5),
+            (33, 5),
+            (64, 5),
+            (65, 5),
+            (300, 20),
+        ] {
+            let coeffs = ints(len as u64, len, 1 << 40);
+            let ys = ints(points as u64 + 7, points, 1000);
+            let expected: Vec<BigInt> = ys.iter().map(|y| horner(&coeffs, y)).collect();
+            assert_eq!(
+                eval_integers(&coeffs, &ys),
+                expected,
+                "{} coefficients",
+                len
+            );
+        }
+        assert!(eval_integers(&ints(3, 50, 10), &[]).is_empty());
+    }
+
+    #[test]
+    fn eval_each_agrees_with_eval() {
+        let degree = 2 * SPLIT_THRESHOLD;
+        let numers = ints(11, degree + 1, 1 << 20);
+        let poly = Polynomial::from_parts(numers, 360.into(), ());
+        let numerators = ints(5, 40, 500);
+        let xs: Vec<BigRational> = (numerators.into_iter().enumerate())
+            .map(|(i, n)| BigRational::new(n, [1, 1, 3, 1, 7][i % 5].into()))
+            .collect();
+        let expected: Vec<BigRational> = xs.iter().map(|x| poly.eval(x)).collect();
+        assert_eq!(poly.eval_each(&xs), expected);
+
+        let zero = Polynomial::zero(());
+        assert_eq!(
+            zero.eval_each(&xs[..3]),
+            vec![BigRational::from_integer(0.into()); 3]
+        );
+        let start = BigRational::new((-5).into(), 2.into());
+        let step = BigRational::new(1.into(), 3.into());
+        let progression: Vec<BigRational> = (0..70)
+            .map(|i| poly.eval(&(&start + &step * BigRational::from_integer(i.into()))))
+            .collect();
+        assert_eq!(poly.eval_progression(&start, &step, 70), progression);
+    }
+}
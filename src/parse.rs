@@ -1,132 +1,426 @@
-use std::cell::LazyCell;
+use std::fmt;
 
 use num_bigint::BigInt;
 use num_rational::BigRational;
-use num_traits::Zero;
 
 use crate::{
-    pascal::{choose, factorial, pick},
-    polynomial::{x, Polynomial},
+    budget::{Budget, Limit},
+    polynomial::Polynomial,
+    ring::{check_prime_modulus, Coefficient, Fp},
 };
 
+mod ast;
+mod eval;
+mod identity;
+mod points;
 mod poly_to_usize;
 mod stream;
 
-use self::poly_to_usize::poly_to_usize;
+pub(crate) use self::ast::{BinOp, Expr, Function, Statement};
+pub(crate) use self::identity::{Identity, Witness};
+pub(crate) use self::points::{parse_eval_input, EvalInput, EvalPoints};
 use self::stream::Stream;
 
 type Result<T> = std::result::Result<T, String>;
 
-pub(crate) fn parse(input: &str) -> Result<Polynomial> {
-    let mut stream = Stream::new(input);
-    let poly = parse_expr(&mut stream)?;
+/// A parsed expression, over the rationals unless a `mod p:` directive chose GF(p),
+/// or the outcome of comparing two expressions with `==`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Parsed {
+    Rational(Polynomial),
+    Modular { poly: Polynomial<Fp>, modulus: u64 },
+    Identity(Identity),
+}
+
+/// Parses and evaluates an expression in which `ans`, `ans1`, `ans2`, ... refer to
+/// `bindings`; see `parse_to_ast` and `Statement::eval`.
+pub(crate) fn parse(
+    input: &str,
+    bindings: &Bindings,
+    budget: &Budget,
+    powers: Associativity,
+) -> Result<Parsed> {
+    parse_to_ast(input, budget, powers)?.eval(bindings, budget)
+}
+
+// --- Previous Results ---
+/// Earlier results that expressions can refer to, most recent first. `None`
+/// stands for a query that failed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Bindings<'a> {
+    answers: Vec<Option<&'a Parsed>>,
+}
+
+impl<'a> Bindings<'a> {
+    pub(crate) fn new(answers: Vec<Option<&'a Parsed>>) -> Self {
+        Bindings { answers }
+    }
+
+    pub(crate) fn answers(&self) -> &[Option<&'a Parsed>] {
+        &self.answers
+    }
+
+    /// The N-th latest result, as named by `Expr::Answer(n)`.
+    fn get(&self, n: usize) -> Result<&'a Parsed> {
+        match self.answers.get(n - 1) {
+            Some(Some(value)) => Ok(*value),
+            Some(None) => Err(format!(
+                "'{}' refers to a query that failed",
+                Expr::Answer(n)
+            )),
+            None => Err(format!(
+                "'{}' is not defined: there are {} previous results",
+                Expr::Answer(n),
+                self.answers.len()
+            )),
+        }
+    }
+}
+
+/// N for `ans` (the latest result, N = 1) or `ansN`, and `None` for any other name.
+fn answer_index(ident: &str) -> Option<usize> {
+    let index = ident.strip_prefix("ans")?;
+    if index.is_empty() {
+        return Some(1);
+    }
+    match index.parse::<usize>() {
+        Ok(n) if n > 0 && !index.starts_with('0') => Some(n),
+        _ => None,
+    }
+}
+
+/// What evaluating an `Expr` needs besides the expression.
+struct Env<'a, C: Coefficient> {
+    ctx: C::Context,
+    bindings: &'a Bindings<'a>,
+    /// Brings a previous result into the coefficient ring being evaluated in.
+    convert: fn(&Parsed, C::Context) -> Result<Polynomial<C>>,
+    budget: Budget,
+}
+
+impl<C: Coefficient> Clone for Env<'_, C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C: Coefficient> Copy for Env<'_, C> {}
+
+impl<'a, C: Coefficient> Env<'a, C> {
+    /// The environment for exponents and counts, which are exact integers even
+    /// in GF(p): `x^(14/2)` is `x^7` there too.
+    fn exact(self) -> Env<'a, BigRational> {
+        Env {
+            ctx: (),
+            bindings: self.bindings,
+            convert: to_exact_binding,
+            budget: self.budget,
+        }
+    }
+}
+
+fn to_rational_binding(value: &Parsed, (): ()) -> Result<Polynomial> {
+    match value {
+        Parsed::Rational(poly) => Ok(poly.clone()),
+        Parsed::Modular { modulus, .. } => Err(format!(
+            "A result mod {} can only be used after 'mod {}:'",
+            modulus, modulus
+        )),
+        Parsed::Identity(_) => Err(NOT_A_POLYNOMIAL.to_string()),
+    }
+}
+
+fn to_exact_binding(value: &Parsed, (): ()) -> Result<Polynomial> {
+    match value {
+        Parsed::Modular { modulus, .. } => Err(format!(
+            "A result mod {} can't be used where an exact integer is needed",
+            modulus
+        )),
+        _ => to_rational_binding(value, ()),
+    }
+}
+
+fn to_field_binding(value: &Parsed, modulus: u64) -> Result<Polynomial<Fp>> {
+    match value {
+        Parsed::Rational(poly) => to_field_poly(poly, modulus),
+        Parsed::Modular {
+            poly,
+            modulus: value_modulus,
+        } if *value_modulus == modulus => Ok(poly.clone()),
+        Parsed::Modular {
+            modulus: value_modulus,
+            ..
+        } => Err(format!(
+            "A result mod {} can't be used mod {}",
+            value_modulus, modulus
+        )),
+        Parsed::Identity(_) => Err(NOT_A_POLYNOMIAL.to_string()),
+    }
+}
+
+impl fmt::Display for Parsed {
+    /// Writes polynomials in a form `parse` reads back.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Parsed::Rational(poly) => write!(f, "{}", poly),
+            Parsed::Modular { poly, modulus } => write!(f, "mod {}: {}", modulus, poly),
+            Parsed::Identity(identity) => write!(f, "{}", identity.holds),
+        }
+    }
+}
+
+impl Parsed {
+    /// Evaluates at each point. Values in GF(p) are given by their representatives in `0..p`.
+    pub(crate) fn eval(&self, points: &EvalPoints) -> Result<Vec<BigRational>> {
+        match self {
+            Parsed::Rational(poly) => Ok(points.eval(poly)),
+            Parsed::Modular { poly, modulus } => points
+                .xs()
+                .iter()
+                .map(|x| Ok(poly.eval(&to_field(x, *modulus)?).lift()))
+                .collect(),
+            Parsed::Identity(_) => Err(NOT_A_POLYNOMIAL.to_string()),
+        }
+    }
+
+    /// Substitutes `inner` for `x`.
+    pub(crate) fn compose(&self, inner: &Polynomial) -> Result<Parsed> {
+        match self {
+            Parsed::Rational(poly) => Ok(Parsed::Rational(poly.compose(inner))),
+            Parsed::Modular { poly, modulus } => {
+                let inner = to_field_poly(inner, *modulus)?;
+                Ok(Parsed::Modular {
+                    poly: poly.compose(&inner),
+                    modulus: *modulus,
+                })
+            }
+            Parsed::Identity(_) => Err(NOT_A_POLYNOMIAL.to_string()),
+        }
+    }
+}
+
+const NOT_A_POLYNOMIAL: &str = "The result of a comparison is not a polynomial";
+
+fn to_field(r: &BigRational, modulus: u64) -> Result<Fp> {
+    Fp::from_rational(r, modulus).ok_or_else(|| format!("{} is undefined mod {}", r, modulus))
+}
+
+fn to_field_poly(poly: &Polynomial, modulus: u64) -> Result<Polynomial<Fp>> {
+    let terms = poly
+        .terms()
+        .iter()
+        .map(|(k, c)| Ok((*k, to_field(c, modulus)?)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Polynomial::from_terms(terms, modulus))
+}
+
+/// Splits a leading `mod p:` directive from the expression that follows it.
+fn split_modulus_directive(input: &str) -> Result<(Option<u64>, &str)> {
+    let Some(rest) = input.trim_start().strip_prefix("mod") else {
+        return Ok((None, input));
+    };
+    if rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Ok((None, input));
+    }
+    let Some((modulus, expr)) = rest.split_once(':') else {
+        return Err("Expected ':' after 'mod p'".to_string());
+    };
+    let modulus = modulus
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| format!("Invalid modulus '{}'", modulus.trim()))?;
+    check_prime_modulus(modulus)?;
+    Ok((Some(modulus), expr))
+}
+
+// --- Parsing ---
+/// How runs of `^` group: `x^y^z` is `x^(y^z)` when they associate to the right,
+/// as in mathematical convention, and `(x^y)^z` when to the left, as this
+/// calculator used to read it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Associativity {
+    Left,
+    Right,
+}
+
+impl Associativity {
+    /// The value used for this setting in its selector, in storage and by the worker.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Associativity::Left => "left",
+            Associativity::Right => "right",
+        }
+    }
+
+    pub(crate) fn by_name(name: &str) -> Option<Associativity> {
+        match name {
+            "left" => Some(Associativity::Left),
+            "right" => Some(Associativity::Right),
+            _ => None,
+        }
+    }
+}
+
+/// Where the parser is: how many subexpressions enclose the one being parsed,
+/// which `Budget::max_depth` bounds, and how it reads `^`.
+#[derive(Debug, Clone, Copy)]
+struct Scope {
+    level: u64,
+    budget: Budget,
+    powers: Associativity,
+}
+
+impl Scope {
+    fn new(budget: &Budget, powers: Associativity) -> Self {
+        Scope {
+            level: 0,
+            budget: *budget,
+            powers,
+        }
+    }
+
+    /// One level of nesting further in.
+    fn nested(self) -> Result<Self> {
+        let level = self.level + 1;
+        self.budget.check(Limit::Depth, level)?;
+        Ok(Scope { level, ..self })
+    }
+}
+
+/// Parses `input` into a `Statement` without computing anything, so that it can be
+/// shown back or evaluated under different bindings.
+pub(crate) fn parse_to_ast(
+    input: &str,
+    budget: &Budget,
+    powers: Associativity,
+) -> Result<Statement> {
+    let (modulus, rest) = split_modulus_directive(input)?;
+    let scope = Scope::new(budget, powers);
+    let mut stream = Stream::new(rest);
+    let lhs = parse_expr(&mut stream, scope)?;
+    let rhs = if stream.take_char('=') {
+        if !stream.take_char('=') {
+            return Err("Expected '==' to compare two expressions".to_string());
+        }
+        Some(parse_expr(&mut stream, scope)?)
+    } else {
+        None
+    };
     match stream.finish() {
-        Ok(()) => Ok(poly),
+        Ok(()) => Ok(Statement { modulus, lhs, rhs }),
         Err(remainder) => Err(format!("Unexpected input after parsing: '{}'", remainder)),
     }
 }
 
-fn parse_expr(input: &mut Stream) -> Result<Polynomial> {
-    let mut poly = parse_term(input)?;
+/// `first`, or the chain of it and `ops` if there are any.
+fn chain(first: Expr, ops: Vec<(BinOp, Expr)>) -> Expr {
+    if ops.is_empty() {
+        first
+    } else {
+        Expr::Chain(Box::new(first), ops)
+    }
+}
+
+fn parse_expr(input: &mut Stream, scope: Scope) -> Result<Expr> {
+    let scope = scope.nested()?;
+    let first = parse_term(input, scope)?;
+    let mut ops = Vec::new();
     loop {
         if input.take_char('+') {
-            poly += parse_term(input)?;
+            ops.push((BinOp::Add, parse_term(input, scope)?));
         } else if input.take_char('-') {
-            poly -= parse_term(input)?;
+            ops.push((BinOp::Sub, parse_term(input, scope)?));
         } else {
             break;
         }
     }
-    Ok(poly)
+    Ok(chain(first, ops))
 }
 
-fn parse_term(input: &mut Stream) -> Result<Polynomial> {
-    let mut poly = parse_factor(input)?;
+fn parse_term(input: &mut Stream, scope: Scope) -> Result<Expr> {
+    let first = parse_factor(input, scope)?;
+    let mut ops = Vec::new();
     loop {
         if input.take_char('*') {
-            poly *= parse_factor(input)?;
+            ops.push((BinOp::Mul, parse_factor(input, scope)?));
         } else if input.take_char('/') {
-            let rhs = parse_factor(input)?;
-            let Some(divisor) = rhs.extract_constant() else {
-                return Err(format!(
-                    "Division must be by a constant number, not a polynomial \
-                    containing 'x'. Problem term: {}",
-                    rhs
-                ));
-            };
-            if divisor.is_zero() {
-                return Err("Division by zero is not allowed.".to_string());
-            }
-            poly /= &divisor;
-        } else if let Some(next_char) = input.peek_char() {
-            if next_char.is_ascii_alphabetic() || next_char == '(' {
-                poly *= parse_factor(input)?;
-                continue;
-            }
-            break;
+            ops.push((BinOp::Div, parse_factor(input, scope)?));
+        } else if input
+            .peek_char()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '(')
+        {
+            ops.push((BinOp::Mul, parse_factor(input, scope)?));
         } else {
             break;
         }
     }
-    Ok(poly)
+    Ok(chain(first, ops))
 }
 
-fn parse_factor(input: &mut Stream) -> Result<Polynomial> {
+fn parse_factor(input: &mut Stream, scope: Scope) -> Result<Expr> {
     if input.take_char('-') {
-        Ok(-parse_factor(input)?)
+        Ok(Expr::Neg(Box::new(parse_factor(input, scope.nested()?)?)))
     } else {
-        parse_power(input)
+        parse_power(input, scope)
     }
 }
 
-fn parse_power(input: &mut Stream) -> Result<Polynomial> {
-    let mut base = parse_postfix(input)?;
-    loop {
-        if input.take_char('^') {
-            let exponent = parse_postfix(input)?;
-            let exp_val = poly_to_usize(&exponent, "Exponent")?;
-            base = base.pow(exp_val);
-        } else {
-            break;
+fn parse_power(input: &mut Stream, mut scope: Scope) -> Result<Expr> {
+    let mut operands = vec![parse_postfix(input, scope)?];
+    while input.take_char('^') {
+        if scope.powers == Associativity::Right {
+            // Each exponent holds the rest of the run, one level further in
+            scope = scope.nested()?;
         }
+        operands.push(parse_postfix(input, scope)?);
     }
-    Ok(base)
+    let mut operands = operands.into_iter();
+    Ok(match scope.powers {
+        Associativity::Left => {
+            let base = operands.next().unwrap();
+            chain(
+                base,
+                operands.map(|exponent| (BinOp::Pow, exponent)).collect(),
+            )
+        }
+        Associativity::Right => operands
+            .rev()
+            .reduce(|exponent, base| Expr::Chain(Box::new(base), vec![(BinOp::Pow, exponent)]))
+            .unwrap(),
+    })
 }
 
-fn parse_postfix(input: &mut Stream) -> Result<Polynomial> {
-    let mut poly = parse_primary(input)?;
-    loop {
-        if input.take_char('!') {
-            let n = poly_to_usize(&poly, "Operand for !")?;
-            poly = Polynomial::constant(factorial(n).into());
-        } else {
-            break;
-        }
+fn parse_postfix(input: &mut Stream, mut scope: Scope) -> Result<Expr> {
+    let mut expr = parse_primary(input, scope)?;
+    while input.take_char('!') {
+        scope = scope.nested()?;
+        expr = Expr::Factorial(Box::new(expr));
     }
-    Ok(poly)
+    Ok(expr)
 }
 
-fn parse_primary(input: &mut Stream) -> Result<Polynomial> {
+fn parse_primary(input: &mut Stream, scope: Scope) -> Result<Expr> {
     if input.take_char('(') {
-        let poly = parse_expr(input)?;
+        let expr = parse_expr(input, scope)?;
         if !input.take_char(')') {
             return Err("Mismatched parentheses".to_string());
         }
-        Ok(poly)
+        Ok(expr)
     } else if input.peek_char().is_some_and(|c| c.is_ascii_alphabetic()) {
-        let ident = input.parse_all_matching(char::is_ascii_alphabetic);
+        let ident = input.parse_all_matching(char::is_ascii_alphanumeric);
         if input.take_char('(') {
-            let args = parse_args(input)?;
+            let args = parse_args(input, scope)?;
             if !input.take_char(')') {
                 return Err("Expected ')' to close function call".to_string());
             }
-            match ident {
-                "P" => parse_function_call(&args, 'P', pick),
-                "C" => parse_function_call(&args, 'C', choose),
-                _ => Err(format!("Unknown function '{}'", ident)),
+            match Function::by_name(ident) {
+                Some(function) => Ok(Expr::Call(function, args)),
+                None => Err(format!("Unknown function '{}'", ident)),
             }
         } else if ident == "x" {
-            Ok(x())
+            Ok(Expr::X)
+        } else if let Some(n) = answer_index(ident) {
+            Ok(Expr::Answer(n))
         } else {
             Err(format!(
                 "Unexpected identifier '{}' without function call",
@@ -138,29 +432,13 @@ fn parse_primary(input: &mut Stream) -> Result<Polynomial> {
     }
 }
 
-fn parse_function_call(
-    args: &[Polynomial],
-    fn_name: char,
-    f: impl FnOnce(&Polynomial, usize) -> Polynomial,
-) -> Result<Polynomial> {
-    if args.len() != 2 {
-        return Err(format!(
-            "Function {} takes 2 arguments, got {}",
-            fn_name,
-            args.len()
-        ));
-    }
-    let pos = LazyCell::new(|| format!("Second argument to {}", fn_name));
-    Ok(f(&args[0], poly_to_usize(&args[1], pos)?))
-}
-
-fn parse_args(input: &mut Stream) -> Result<Vec<Polynomial>> {
+fn parse_args(input: &mut Stream, scope: Scope) -> Result<Vec<Expr>> {
     let mut args = Vec::new();
     if input.peek_char() == Some(')') {
         return Ok(args);
     }
     loop {
-        let arg = parse_expr(input)?;
+        let arg = parse_expr(input, scope)?;
         args.push(arg);
         if input.peek_char() == Some(')') {
             break;
@@ -172,10 +450,10 @@ fn parse_args(input: &mut Stream) -> Result<Vec<Polynomial>> {
     Ok(args)
 }
 
-fn parse_number(input: &mut Stream) -> Result<Polynomial> {
+fn parse_number(input: &mut Stream) -> Result<Expr> {
     let num_str = input.parse_all_matching(char::is_ascii_digit);
     match num_str.parse::<BigInt>() {
-        Ok(val) => Ok(Polynomial::constant(BigRational::from(val))),
+        Ok(val) => Ok(Expr::Number(val)),
         Err(_) => Err(format!("Invalid number format for '{}'", num_str)),
     }
 }
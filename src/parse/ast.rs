@@ -0,0 +1,199 @@
+use std::fmt;
+
+use num_bigint::BigInt;
+
+/// A parsed input: an expression, or two compared with `==`, over GF(p) if a
+/// `mod p:` directive came first. `eval` computes it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Statement {
+    pub(crate) modulus: Option<u64>,
+    pub(crate) lhs: Expr,
+    pub(crate) rhs: Option<Expr>,
+}
+
+/// The structure of an expression, before any of it is computed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Expr {
+    Number(BigInt),
+    X,
+    /// `ans` for 1, `ansN` for N: the N-th latest result.
+    Answer(usize),
+    Neg(Box<Expr>),
+    /// Operators of equal precedence applied left to right, so `a - b + c` is
+    /// `Chain(a, [(Sub, b), (Add, c)])`. A long sum stays one level deep.
+    /// Right-associative powers nest instead, each exponent a chain of its own.
+    Chain(Box<Expr>, Vec<(BinOp, Expr)>),
+    Factorial(Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BinOp {
+    Add,
+    Sub,
+    /// Written `*` or implied, as in `2x`.
+    Mul,
+    Div,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Function {
+    /// `P(n, k)`, the falling factorial.
+    Pick,
+    /// `C(n, k)`, the binomial coefficient.
+    Choose,
+    /// `B(b_0, ..., b_n)`, the polynomial `b_0 + b_1*C(x, 1) + ... + b_n*C(x, n)`
+    /// given by its coefficients in the binomial basis.
+    BinomialBasis,
+}
+
+impl Function {
+    pub(crate) fn by_name(name: &str) -> Option<Function> {
+        match name {
+            "P" => Some(Function::Pick),
+            "C" => Some(Function::Choose),
+            "B" => Some(Function::BinomialBasis),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(&self) -> char {
+        match self {
+            Function::Pick => 'P',
+            Function::Choose => 'C',
+            Function::BinomialBasis => 'B',
+        }
+    }
+}
+
+// --- Pretty-Printing ---
+// `Display` writes expressions back in the syntax `parse_to_ast` reads, with only
+// the parentheses that precedence requires and `*` for implied multiplication.
+// The alternate form, `{:#}`, puts every operation but the outermost in
+// parentheses, to show how the input was grouped.
+
+/// How tightly each kind of expression binds, loosest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Sum,
+    Product,
+    Negation,
+    Power,
+    Postfix,
+    Atom,
+}
+
+impl BinOp {
+    fn precedence(self) -> Precedence {
+        match self {
+            BinOp::Add | BinOp::Sub => Precedence::Sum,
+            BinOp::Mul | BinOp::Div => Precedence::Product,
+            BinOp::Pow => Precedence::Power,
+        }
+    }
+
+    pub(crate) fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => " + ",
+            BinOp::Sub => " - ",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Pow => "^",
+        }
+    }
+}
+
+impl Expr {
+    fn precedence(&self) -> Precedence {
+        match self {
+            Expr::Number(_) | Expr::X | Expr::Answer(_) | Expr::Call(..) => Precedence::Atom,
+            Expr::Neg(_) => Precedence::Negation,
+            Expr::Chain(_, ops) => ops
+                .first()
+                .map_or(Precedence::Atom, |(op, _)| op.precedence()),
+            Expr::Factorial(_) => Precedence::Postfix,
+        }
+    }
+
+    /// Writes `self`, in parentheses unless it binds at least as tightly as `min`,
+    /// or in the alternate form unless it's an atom.
+    fn fmt_within(&self, f: &mut fmt::Formatter<'_>, min: Precedence) -> fmt::Result {
+        let min = if f.alternate() { Precedence::Atom } else { min };
+        if self.precedence() < min {
+            f.write_str("(")?;
+            fmt::Display::fmt(self, f)?;
+            f.write_str(")")
+        } else {
+            fmt::Display::fmt(self, f)
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::X => f.write_str("x"),
+            Expr::Answer(1) => f.write_str("ans"),
+            Expr::Answer(n) => write!(f, "ans{}", n),
+            Expr::Neg(operand) => {
+                f.write_str("-")?;
+                operand.fmt_within(f, Precedence::Negation)
+            }
+            Expr::Chain(first, ops) => {
+                // Parsing flattens unparenthesized runs into one chain, so an
+                // operand that is a chain at the same level was in parentheses.
+                let operand = match self.precedence() {
+                    Precedence::Sum => Precedence::Product,
+                    Precedence::Product => Precedence::Negation,
+                    _ => Precedence::Postfix,
+                };
+                // In full, `a - b + c` is `(a - b) + c`
+                let grouped = if f.alternate() {
+                    ops.len().saturating_sub(1)
+                } else {
+                    0
+                };
+                f.write_str(&"(".repeat(grouped))?;
+                first.fmt_within(f, operand)?;
+                for (i, (op, rhs)) in ops.iter().enumerate() {
+                    if i > 0 && i <= grouped {
+                        f.write_str(")")?;
+                    }
+                    f.write_str(op.symbol())?;
+                    rhs.fmt_within(f, operand)?;
+                }
+                Ok(())
+            }
+            Expr::Factorial(operand) => {
+                operand.fmt_within(f, Precedence::Postfix)?;
+                f.write_str("!")
+            }
+            Expr::Call(function, args) => {
+                write!(f, "{}(", function.name())?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    fmt::Display::fmt(arg, f)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(modulus) = self.modulus {
+            write!(f, "mod {}: ", modulus)?;
+        }
+        fmt::Display::fmt(&self.lhs, f)?;
+        if let Some(rhs) = &self.rhs {
+            f.write_str(" == ")?;
+            fmt::Display::fmt(rhs, f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+use std::cell::LazyCell;
+
+use num_bigint::BigInt;
+
+use super::ast::{BinOp, Expr, Function, Statement};
+use super::identity::compare;
+use super::poly_to_usize::poly_to_usize;
+use super::{to_field_binding, to_rational_binding, Bindings, Env, Parsed, Result};
+use crate::budget::Budget;
+use crate::interrupt;
+use crate::pascal::{check_pick, checked_factorial, choose, pick};
+use crate::polynomial::{x, Polynomial};
+use crate::ring::Coefficient;
+use crate::trace::{self, Position};
+
+// --- Evaluation ---
+impl Statement {
+    /// Computes the statement, with `ans`, `ans1`, `ans2`, ... referring to `bindings`.
+    ///
+    /// Long computations stop with an error if `interrupt::checkpoint` reports a
+    /// cancellation, and don't start if they would exceed `budget`.
+    pub(crate) fn eval(&self, bindings: &Bindings, budget: &Budget) -> Result<Parsed> {
+        interrupt::reset();
+        match self.modulus {
+            Some(modulus) => {
+                let env = Env {
+                    ctx: modulus,
+                    bindings,
+                    convert: to_field_binding,
+                    budget: *budget,
+                };
+                let (poly, rhs) = self.eval_sides(env)?;
+                Ok(match rhs {
+                    Some(rhs) => Parsed::Identity(compare(&poly, &rhs)),
+                    None => Parsed::Modular { poly, modulus },
+                })
+            }
+            None => {
+                let env = Env {
+                    ctx: (),
+                    bindings,
+                    convert: to_rational_binding,
+                    budget: *budget,
+                };
+                let (lhs, rhs) = self.eval_sides(env)?;
+                Ok(match rhs {
+                    Some(rhs) => Parsed::Identity(compare(&lhs, &rhs)),
+                    None => Parsed::Rational(lhs),
+                })
+            }
+        }
+    }
+
+    fn eval_sides<C: Coefficient>(
+        &self,
+        env: Env<C>,
+    ) -> Result<(Polynomial<C>, Option<Polynomial<C>>)> {
+        let lhs = self.lhs.eval(env)?;
+        let rhs = self.rhs.as_ref().map(|rhs| rhs.eval(env)).transpose()?;
+        Ok((lhs, rhs))
+    }
+}
+
+/// The integer `n` as a constant polynomial in `env`.
+fn constant<C: Coefficient>(n: BigInt, env: Env<C>) -> Polynomial<C> {
+    Polynomial::constant(C::from_integer(n, env.ctx), env.ctx)
+}
+
+impl Expr {
+    pub(super) fn eval<C: Coefficient>(&self, env: Env<C>) -> Result<Polynomial<C>> {
+        match self {
+            Expr::Number(n) => Ok(constant(n.clone(), env)),
+            Expr::X => Ok(x(env.ctx)),
+            Expr::Answer(n) => {
+                trace::step(|| self.to_string());
+                let poly = (env.convert)(env.bindings.get(*n)?, env.ctx)?;
+                trace::conclude(&poly);
+                Ok(poly)
+            }
+            Expr::Neg(operand) => {
+                let operand = operand.eval(env)?;
+                trace::step(|| format!("-{}", trace::operand(&operand, Position::Factor)));
+                let poly = -operand;
+                trace::conclude(&poly);
+                Ok(poly)
+            }
+            Expr::Chain(first, ops) => {
+                let mut poly = first.eval(env)?;
+                for (op, operand) in ops {
+                    poly = match op {
+                        BinOp::Pow => power(poly, operand, env)?,
+                        _ => apply(*op, poly, operand.eval(env)?, env)?,
+                    };
+                }
+                Ok(poly)
+            }
+            Expr::Factorial(operand) => {
+                let n = poly_to_usize(&operand.eval(env.exact())?, "Operand for !")?;
+                trace::step(|| format!("{}!", n));
+                let poly = constant(checked_factorial(n, &env.budget)?, env);
+                interrupt::check()?;
+                trace::conclude(&poly);
+                Ok(poly)
+            }
+            Expr::Call(function, args) => call(*function, args, env),
+        }
+    }
+}
+
+fn apply<C: Coefficient>(
+    op: BinOp,
+    lhs: Polynomial<C>,
+    rhs: Polynomial<C>,
+    env: Env<C>,
+) -> Result<Polynomial<C>> {
+    trace::step(|| {
+        let (lhs, rhs) = match op {
+            BinOp::Add | BinOp::Sub => (lhs.to_string(), trace::operand(&rhs, Position::Summand)),
+            BinOp::Mul | BinOp::Div => (
+                trace::operand(&lhs, Position::Factor),
+                trace::operand(&rhs, Position::Factor),
+            ),
+            BinOp::Pow => unreachable!("powers are applied by `power`"),
+        };
+        format!("{}{}{}", lhs, op.symbol(), rhs)
+    });
+    let result = match op {
+        BinOp::Add => lhs + rhs,
+        BinOp::Sub => lhs - rhs,
+        BinOp::Mul => lhs.checked_mul(rhs, &env.budget)?,
+        BinOp::Div => {
+            let Some(divisor) = rhs.extract_constant() else {
+                return Err(format!(
+                    "Division must be by a constant number, not a polynomial \
+                    containing 'x'. Problem term: {}",
+                    rhs
+                ));
+            };
+            lhs.checked_div(&divisor)
+                .ok_or_else(|| "Division by zero is not allowed.".to_string())?
+        }
+        BinOp::Pow => unreachable!("powers are applied by `power`"),
+    };
+    interrupt::check()?;
+    trace::conclude(&result);
+    Ok(result)
+}
+
+/// `base^exponent`, with the exponent an exact integer.
+fn power<C: Coefficient>(
+    base: Polynomial<C>,
+    exponent: &Expr,
+    env: Env<C>,
+) -> Result<Polynomial<C>> {
+    let n = poly_to_usize(&exponent.eval(env.exact())?, "Exponent")?;
+    trace::step(|| format!("{}^{}", trace::operand(&base, Position::Base), n));
+    let result = base.checked_pow(n, &env.budget)?;
+    interrupt::check()?;
+    trace::conclude(&result);
+    Ok(result)
+}
+
+fn call<C: Coefficient>(function: Function, args: &[Expr], env: Env<C>) -> Result<Polynomial<C>> {
+    let fn_name = function.name();
+    if function == Function::BinomialBasis {
+        return binomial_basis(args, env);
+    }
+    if args.len() != 2 {
+        return Err(format!(
+            "Function {} takes 2 arguments, got {}",
+            fn_name,
+            args.len()
+        ));
+    }
+    let pos = LazyCell::new(|| format!("Second argument to {}", fn_name));
+    let k = poly_to_usize(&args[1].eval(env.exact())?, pos)?;
+    let characteristic = C::characteristic(env.ctx);
+    if function == Function::Choose && characteristic != 0 && k as u64 >= characteristic {
+        // `k!` is zero here, so divide it out exactly and reduce the quotient:
+        // `C(10, 7)` is 120, which is 1 mod 7.
+        let exact = call(function, args, env.exact())?;
+        return (env.convert)(&Parsed::Rational(exact), env.ctx)
+            .map_err(|e| format!("C(_, {}) is undefined here: {}", k, e));
+    }
+    let poly = args[0].eval(env)?;
+    trace::step(|| format!("{}({}, {})", fn_name, poly, k));
+    check_pick(&poly, k, &env.budget)?;
+    let result = match function {
+        Function::Pick => pick(&poly, k),
+        Function::Choose => choose(&poly, k)
+            .ok_or_else(|| format!("C(_, {}) is undefined here: {}! is not invertible", k, k))?,
+        Function::BinomialBasis => unreachable!("`B` takes any number of arguments"),
+    };
+    interrupt::check()?;
+    trace::conclude(&result);
+    Ok(result)
+}
+
+/// `B(b_0, ..., b_n)`, with the coefficients exact constants.
+fn binomial_basis<C: Coefficient>(args: &[Expr], env: Env<C>) -> Result<Polynomial<C>> {
+    if args.is_empty() {
+        return Err("Function B takes at least 1 argument".to_string());
+    }
+    let coeffs = args
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| {
+            let value = arg.eval(env.exact())?;
+            value
+                .extract_constant()
+                .ok_or_else(|| format!("Argument {} to B must be a constant, got {}", i + 1, value))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    trace::step(|| {
+        let coeffs: Vec<String> = coeffs.iter().map(ToString::to_string).collect();
+        format!("B({})", coeffs.join(", "))
+    });
+    // The coefficients of the result are sums over `n!`.
+    checked_factorial(coeffs.len() - 1, &env.budget)?;
+    let exact = Polynomial::from_binomial_coeffs(&coeffs);
+    let result = (env.convert)(&Parsed::Rational(exact), env.ctx)
+        .map_err(|e| format!("B(...) is undefined here: {}", e))?;
+    interrupt::check()?;
+    trace::conclude(&result);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::budget::Budget;
+    use crate::parse::{parse, Associativity, Bindings};
+
+    fn eval(input: &str) -> Result<String, String> {
+        let parsed = parse(
+            input,
+            &Bindings::default(),
+            &Budget::default(),
+            Associativity::Right,
+        )?;
+        Ok(parsed.to_string())
+    }
+
+    #[test]
+    fn choose_divides_exactly_when_k_factorial_vanishes_mod_p() {
+        assert_eq!(eval("mod 7: C(10, 7)").unwrap(), "mod 7: 1");
+        assert_eq!(eval("mod 7: C(10, 3)").unwrap(), "mod 7: 1");
+        assert!(eval("mod 7: C(x, 7)").is_err());
+    }
+
+    #[test]
+    fn exponents_are_exact_integers_mod_p() {
+        assert_eq!(eval("mod 7: x^(14/2)").unwrap(), "mod 7: x^7");
+        assert_eq!(eval("mod 7: x^7").unwrap(), "mod 7: x^7");
+        assert_eq!(eval("mod 7: 2^(3!)").unwrap(), "mod 7: 1");
+    }
+
+    #[test]
+    fn binomial_basis_coefficients_build_a_polynomial() {
+        assert_eq!(eval("B(1, 2, 3)").unwrap(), "(3/2)*x^2 + (1/2)*x + 1");
+        assert_eq!(eval("mod 7: B(1, 2, 3)").unwrap(), "mod 7: 5*x^2 + 4*x + 1");
+        assert!(eval("B(x)").is_err());
+        assert!(eval("B()").is_err());
+    }
+
+    #[test]
+    fn literals_are_reduced_mod_p() {
+        assert_eq!(eval("mod 7: 10*x + 15").unwrap(), "mod 7: 3*x + 1");
+        assert_eq!(eval("mod 7: P(x + 7, 2)").unwrap(), "mod 7: x^2 + 6*x");
+    }
+}
@@ -0,0 +1,149 @@
+use num_rational::BigRational;
+
+use crate::polynomial::Polynomial;
+use crate::ring::Coefficient;
+
+/// The outcome of `p == q`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Identity {
+    pub(crate) holds: bool,
+    /// Where the two sides differ, if they do at some point of the coefficient
+    /// field. Over GF(p), different polynomials can agree at every point.
+    pub(crate) witness: Option<Witness>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Witness {
+    pub(crate) x: BigRational,
+    pub(crate) lhs: BigRational,
+    pub(crate) rhs: BigRational,
+}
+
+/// Compares two polynomials coefficient by coefficient. If they differ, their
+/// difference has at most `degree` roots, so one of `0..=degree` is a witness
+/// unless the field is too small to hold that many points. Over GF(p) the
+/// search stops at `p`.
+pub(crate) fn compare<C: Coefficient>(lhs: &Polynomial<C>, rhs: &Polynomial<C>) -> Identity {
+    if lhs == rhs {
+        return Identity {
+            holds: true,
+            witness: None,
+        };
+    }
+    let ctx = lhs.ctx();
+    let degree = (lhs.clone() - rhs.clone()).degree() as u64;
+    let points = match C::characteristic(ctx) {
+        0 => degree + 1,
+        p => p.min(degree + 1),
+    };
+    let witness = (0..points).find_map(|k| {
+        let x = C::from_integer(k.into(), ctx);
+        let (lhs, rhs) = (lhs.eval(&x), rhs.eval(&x));
+        (lhs != rhs).then(|| Witness {
+            x: BigRational::from_integer(k.into()),
+            lhs: lhs.lift(),
+            rhs: rhs.lift(),
+        })
+    });
+    Identity {
+        holds: false,
+        witness,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+
+    use super::{compare, Identity, Witness};
+    use crate::budget::Budget;
+    use crate::parse::{parse, Associativity, Bindings, Parsed};
+    use crate::polynomial::{x, Polynomial};
+    use crate::ring::{Coefficient, Fp};
+
+    fn identity(query: &str) -> Identity {
+        let parsed = parse(
+            query,
+            &Bindings::default(),
+            &Budget::default(),
+            Associativity::Right,
+        );
+        match parsed.unwrap() {
+            Parsed::Identity(identity) => identity,
+            other => panic!("{} is not an identity: {}", query, other),
+        }
+    }
+
+    fn witness(x: i64, lhs: BigRational, rhs: BigRational) -> Option<Witness> {
+        let x = BigRational::from_integer(x.into());
+        Some(Witness { x, lhs, rhs })
+    }
+
+    fn q(n: i64, d: i64) -> BigRational {
+        BigRational::new(n.into(), d.into())
+    }
+
+    #[test]
+    fn identities_that_hold_have_no_witness() {
+        let holds = Identity {
+            holds: true,
+            witness: None,
+        };
+        assert_eq!(identity("(x + 1)^2 == x^2 + 2*x + 1"), holds);
+        assert_eq!(identity("C(x + 1, 2) == C(x, 2) + x"), holds);
+        assert_eq!(identity("x/2 + x/3 == 5/6*x"), holds);
+        assert_eq!(identity("mod 7: (x + 1)^7 == x^7 + 1"), holds);
+    }
+
+    #[test]
+    fn rational_identities_that_fail_have_a_witness() {
+        let fails = |x, lhs, rhs| Identity {
+            holds: false,
+            witness: witness(x, lhs, rhs),
+        };
+        // x^2 and x agree at 0 and 1.
+        assert_eq!(identity("x^2 == x"), fails(2, q(4, 1), q(2, 1)));
+        assert_eq!(identity("x/2 == 0"), fails(1, q(1, 2), q(0, 1)));
+        assert_eq!(identity("3 == 5/2"), fails(0, q(3, 1), q(5, 2)));
+        // x(x - 1)(x - 2) vanishes at 0, 1 and 2, but not at every point.
+        assert_eq!(identity("P(x, 3) == 0"), fails(3, q(6, 1), q(0, 1)));
+        assert_eq!(identity("mod 7: x^2 == x"), fails(2, q(4, 1), q(2, 1)));
+    }
+
+    #[test]
+    fn different_polynomials_can_agree_everywhere_mod_p() {
+        let no_witness = Identity {
+            holds: false,
+            witness: None,
+        };
+        assert_eq!(identity("mod 7: x^7 == x"), no_witness);
+        assert_eq!(identity("mod 5: x^9 == x"), no_witness);
+        assert_eq!(identity("mod 2: x^2 + x == 0"), no_witness);
+        assert_eq!(identity("mod 3: P(x, 3) == 0"), no_witness);
+    }
+
+    #[test]
+    fn zero_is_canonical_mod_p() {
+        let zero = Polynomial::<Fp>::zero(7);
+        let seven = Fp::from_integer(7.into(), 7);
+        let sparse_zeros = BTreeMap::from([(2, BigInt::from(14)), (90, BigInt::from(-7))]);
+        for poly in [
+            Polynomial::<Fp>::from_parts(vec![7.into(), 0.into(), 21.into()], 3.into(), 7),
+            Polynomial::from_sparse(sparse_zeros, 1.into(), 7),
+            Polynomial::constant(seven, 7),
+            x(7) * &seven,
+            x(7).pow(7) - x(7).pow(7),
+            (x(7) + seven) * Polynomial::zero(7),
+        ] {
+            assert_eq!(poly, zero);
+            assert!(poly.is_zero());
+            assert!(compare(&poly, &zero).holds);
+        }
+        assert!(identity("mod 7: 7*x^100 + 14 == 0").holds);
+        assert!(identity("x - x == 0").holds);
+        assert!(identity("C(x, 3) - P(x, 3)/6 == 0*x^5").holds);
+    }
+}
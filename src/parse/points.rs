@@ -0,0 +1,130 @@
+use num_rational::BigRational;
+use num_traits::{One, ToPrimitive};
+
+use super::stream::Stream;
+use super::{parse_expr, to_rational_binding, Associativity, Bindings, Env, Result, Scope};
+use crate::budget::Budget;
+use crate::polynomial::Polynomial;
+
+/// Ranges producing more rows than this are rejected rather than rendered.
+const MAX_TABLE_ROWS: usize = 10_000;
+
+/// The points entered in the evaluation box.
+pub(crate) enum EvalPoints {
+    Single(BigRational),
+    /// Every `start + k` up to and including `end`.
+    Range {
+        start: BigRational,
+        count: usize,
+    },
+    List(Vec<BigRational>),
+}
+
+/// What the evaluation box asks for: values at some points, or the result of
+/// substituting a non-constant argument for `x`.
+pub(crate) enum EvalInput {
+    Points(EvalPoints),
+    Composition(Polynomial),
+}
+
+/// Parses an expression such as `5`, `1/2+1` or `x+1`, an inclusive range
+/// `0..20`, or a list `[1, 1/2, -3]`. Range endpoints and list items may be
+/// any constant expressions, and may refer to previous results as in `parse`.
+pub(crate) fn parse_eval_input(
+    input: &str,
+    bindings: &Bindings,
+    budget: &Budget,
+    powers: Associativity,
+) -> Result<EvalInput> {
+    let env = Env {
+        ctx: (),
+        bindings,
+        convert: to_rational_binding,
+        budget: *budget,
+    };
+    let scope = Scope::new(budget, powers);
+    let mut stream = Stream::new(input);
+    let eval_input = if stream.take_char('[') {
+        let mut points = Vec::new();
+        if !stream.take_char(']') {
+            loop {
+                points.push(parse_point(&mut stream, scope, env, "List item")?);
+                if stream.take_char(']') {
+                    break;
+                }
+                if !stream.take_char(',') {
+                    return Err("Expected ',' or ']' in list of points".to_string());
+                }
+            }
+        }
+        if points.len() > MAX_TABLE_ROWS {
+            return Err(format!("At most {} points are allowed", MAX_TABLE_ROWS));
+        }
+        EvalInput::Points(EvalPoints::List(points))
+    } else {
+        let arg = parse_expr(&mut stream, scope)?.eval(env)?;
+        if stream.take_char('.') {
+            if !stream.take_char('.') {
+                return Err("Expected '..' in range".to_string());
+            }
+            let start = to_point(arg, "Range start")?;
+            let end = parse_point(&mut stream, scope, env, "Range end")?;
+            if end < start {
+                return Err(format!("Range end {} is before its start {}", end, start));
+            }
+            let count = ((end - &start).floor().to_integer() + 1u32)
+                .to_usize()
+                .filter(|&count| count <= MAX_TABLE_ROWS)
+                .ok_or_else(|| format!("A range may cover at most {} points", MAX_TABLE_ROWS))?;
+            EvalInput::Points(EvalPoints::Range { start, count })
+        } else {
+            match arg.extract_constant() {
+                Some(x) => EvalInput::Points(EvalPoints::Single(x)),
+                None => EvalInput::Composition(arg),
+            }
+        }
+    };
+    match stream.finish() {
+        Ok(()) => Ok(eval_input),
+        Err(remainder) => Err(format!("Unexpected input after parsing: '{}'", remainder)),
+    }
+}
+
+fn parse_point(
+    input: &mut Stream,
+    scope: Scope,
+    env: Env<BigRational>,
+    pos: &str,
+) -> Result<BigRational> {
+    to_point(parse_expr(input, scope)?.eval(env)?, pos)
+}
+
+fn to_point(poly: Polynomial, pos: &str) -> Result<BigRational> {
+    match poly.extract_constant() {
+        Some(x) => Ok(x),
+        None => Err(format!("{} must be a constant, got {}", pos, poly)),
+    }
+}
+
+impl EvalPoints {
+    pub(crate) fn xs(&self) -> Vec<BigRational> {
+        match self {
+            EvalPoints::Single(x) => vec![x.clone()],
+            EvalPoints::Range { start, count } => (0..*count)
+                .map(|i| start + BigRational::from_integer(i.into()))
+                .collect(),
+            EvalPoints::List(xs) => xs.clone(),
+        }
+    }
+
+    /// Evaluates `poly` at each point, in the same order as `xs`.
+    pub(crate) fn eval(&self, poly: &Polynomial) -> Vec<BigRational> {
+        match self {
+            EvalPoints::Single(x) => vec![poly.eval(x)],
+            EvalPoints::Range { start, count } => {
+                poly.eval_progression(start, &BigRational::one(), *count)
+            }
+            EvalPoints::List(xs) => poly.eval_each(xs),
+        }
+    }
+}
@@ -4,9 +4,13 @@ use num_bigint::BigInt;
 use num_traits::{ToPrimitive, Zero};
 
 use crate::polynomial::Polynomial;
+use crate::ring::Coefficient;
 
-pub(super) fn poly_to_usize(poly: &Polynomial, pos: impl ResolvesToStr) -> Result<usize, String> {
-    let Some(n_rational) = poly.extract_constant() else {
+pub(super) fn poly_to_usize<C: Coefficient>(
+    poly: &Polynomial<C>,
+    pos: impl ResolvesToStr,
+) -> Result<usize, String> {
+    let Some(n_rational) = poly.extract_constant().map(|c| c.lift()) else {
         return Err(format!("{} must be a constant, got {}", pos.to_str(), poly));
     };
     if !n_rational.is_integer() {
@@ -1,22 +1,305 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
 use num_bigint::BigInt;
-use num_rational::BigRational;
 use num_traits::One;
 
-use crate::polynomial::Polynomial;
+use crate::budget::{bit_length, Budget, Limit, LimitExceeded};
+use crate::interrupt;
+use crate::polynomial::{check_size, monomial_count, Polynomial};
+use crate::ring::Coefficient;
+use crate::trace::{self, Position};
 
 // --- Math Helper Functions ---
-pub(crate) fn factorial(n: usize) -> BigInt {
-    (1..=n).map(BigInt::from).product()
+/// How many factorials `factorial` remembers.
+const FACTORIAL_CACHE_SIZE: usize = 32;
+
+/// Factorials by argument, each with the time it was last used.
+#[derive(Default)]
+struct FactorialCache {
+    entries: BTreeMap<usize, (BigInt, u64)>,
+    clock: u64,
 }
 
-pub(crate) fn pick(poly: &Polynomial, k: usize) -> Polynomial {
-    let mut result = Polynomial::constant(BigRational::one());
-    for i in 0..k {
-        result *= poly.clone() - BigRational::from_integer(i.into());
+impl FactorialCache {
+    /// The largest cached factorial up to `n!`, marked as used.
+    fn nearest_below(&mut self, n: usize) -> Option<(usize, BigInt)> {
+        self.clock += 1;
+        let (&m, (value, used)) = self.entries.range_mut(..=n).next_back()?;
+        *used = self.clock;
+        Some((m, value.clone()))
+    }
+
+    /// Remembers `n!`, forgetting the least recently used factorial if full.
+    fn insert(&mut self, n: usize, value: BigInt) {
+        if self.entries.len() >= FACTORIAL_CACHE_SIZE {
+            let oldest = self.entries.iter().min_by_key(|(_, (_, used))| *used);
+            if let Some((&m, _)) = oldest {
+                self.entries.remove(&m);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(n, (value, self.clock));
+    }
+}
+
+thread_local! {
+    static FACTORIALS: RefCell<FactorialCache> = RefCell::new(FactorialCache::default());
+}
+
+/// `n!`, extended from the largest cached factorial below it. Evaluating a
+/// polynomial in the binomial basis and `C(x, k)` ask for the same few over and over.
+pub(crate) fn factorial(n: usize) -> BigInt {
+    let cached = FACTORIALS.with(|f| f.borrow_mut().nearest_below(n));
+    let (m, below) = cached.unwrap_or((0, BigInt::from(1)));
+    if m == n {
+        return below;
+    }
+    let result = below * range_product(m + 1, n + 1);
+    if interrupt::check().is_ok() {
+        FACTORIALS.with(|f| f.borrow_mut().insert(n, result.clone()));
     }
     result
 }
 
-pub(crate) fn choose(poly: &Polynomial, k: usize) -> Polynomial {
-    pick(poly, k) / &BigRational::from(factorial(k))
+/// `lo * (lo + 1) * ... * (hi - 1)`, split in halves so that the big
+/// multiplications have factors of similar size.
+fn range_product(lo: usize, hi: usize) -> BigInt {
+    if hi - lo <= 16 {
+        return (lo..hi).fold(BigInt::from(1), |acc, i| acc * i);
+    }
+    if interrupt::checkpoint() {
+        return BigInt::from(1);
+    }
+    let mid = lo + (hi - lo) / 2;
+    range_product(lo, mid) * range_product(mid, hi)
+}
+
+/// `poly * (poly - 1) * ... * (poly - k + 1)`.
+pub(crate) fn pick<C: Coefficient>(poly: &Polynomial<C>, k: usize) -> Polynomial<C> {
+    if k >= 2 {
+        trace::rule("falling factorial");
+        trace::form(|| factors_form(poly, k));
+    }
+    falling(poly, k)
+}
+
+/// `pick` without tracing, for the functions built on it.
+fn falling<C: Coefficient>(poly: &Polynomial<C>, k: usize) -> Polynomial<C> {
+    pick_range(poly, 0, k)
+}
+
+/// `(poly - lo) * ... * (poly - hi + 1)` as a balanced product tree.
+fn pick_range<C: Coefficient>(poly: &Polynomial<C>, lo: usize, hi: usize) -> Polynomial<C> {
+    let ctx = poly.ctx();
+    match hi - lo {
+        0 => Polynomial::constant(C::from_integer(BigInt::one(), ctx), ctx),
+        1 => poly.clone() - C::from_integer(lo.into(), ctx),
+        _ => {
+            if interrupt::checkpoint() {
+                return Polynomial::zero(ctx);
+            }
+            let mid = lo + (hi - lo) / 2;
+            pick_range(poly, lo, mid) * pick_range(poly, mid, hi)
+        }
+    }
+}
+
+/// `pick(poly, k) / k!`. Returns `None` when `k!` isn't invertible in the
+/// coefficients of `poly`, e.g. `k >= p` in GF(p).
+pub(crate) fn choose<C: Coefficient>(poly: &Polynomial<C>, k: usize) -> Option<Polynomial<C>> {
+    if k >= 2 {
+        trace::rule("binomial coefficient");
+        trace::form(|| format!("{}/{}", factors_form(poly, k), factorial(k)));
+    }
+    falling(poly, k).checked_div(&C::from_integer(factorial(k), poly.ctx()))
+}
+
+/// `poly^n` for a polynomial with two terms, each term of the result straight
+/// from the binomial theorem rather than by repeated squaring.
+pub(crate) fn binomial_power<C: Coefficient>(poly: &Polynomial<C>, n: usize) -> Polynomial<C> {
+    let [(j, b), (i, a)] = &poly.terms()[..] else {
+        unreachable!("binomial_power needs two terms");
+    };
+    trace::rule("binomial theorem");
+    trace::form(|| binomial_form((*i, a), (*j, b), n, poly.ctx()));
+    let ctx = poly.ctx();
+    // Numerators over a shared denominator multiply without reducing fractions.
+    let (numers, denom) = C::over_common_denominator(&[a.clone(), b.clone()]);
+    let (a, b) = (&numers[0], &numers[1]);
+    // The powers of `b` are used from the top down, as those of `a` go up.
+    let mut low_powers = vec![BigInt::one()];
+    let mut result_denom = BigInt::one();
+    for _ in 0..n {
+        let mut next = low_powers.last().unwrap() * b;
+        C::reduce(&mut next, ctx);
+        low_powers.push(next);
+        result_denom *= &denom;
+    }
+    let mut terms = BTreeMap::new();
+    let mut high_power = BigInt::one();
+    let mut binomial = BigInt::from(1);
+    for (k, low_power) in low_powers.iter().rev().enumerate() {
+        if interrupt::checkpoint() {
+            return Polynomial::zero(ctx);
+        }
+        let mut numer = &binomial * &high_power * low_power;
+        C::reduce(&mut numer, ctx);
+        terms.insert(i * k + j * (n - k), numer);
+        high_power *= a;
+        C::reduce(&mut high_power, ctx);
+        binomial = binomial * (n - k) / (k + 1);
+    }
+    Polynomial::from_sparse(terms, result_denom, ctx)
+}
+
+// --- Traced Forms ---
+// What `pick`, `choose` and `binomial_power` compute, written out for a trace.
+// Long products and sums show their first few factors or terms and the last.
+
+/// `poly * (poly - 1) * ... * (poly - k + 1)` with the factors expanded.
+fn factors_form<C: Coefficient>(poly: &Polynomial<C>, k: usize) -> String {
+    let factor = |i: usize| {
+        trace::operand(
+            &(poly.clone() - C::from_integer(i.into(), poly.ctx())),
+            Position::Factor,
+        )
+    };
+    elide(k, factor, "*")
+}
+
+/// `C(n, 0)*high^n + C(n, 1)*high^(n - 1)*low + ... + C(n, n)*low^n`.
+fn binomial_form<C: Coefficient>(
+    high: (usize, &C),
+    low: (usize, &C),
+    n: usize,
+    ctx: C::Context,
+) -> String {
+    let monomial = |(k, c): (usize, &C)| Polynomial::from_terms(vec![(k, c.clone())], ctx);
+    let (high, low) = (monomial(high), monomial(low));
+    let one = Polynomial::constant(C::from_integer(BigInt::one(), ctx), ctx);
+    let power = |base: &Polynomial<C>, e: usize| match e {
+        0 => None,
+        _ if *base == one => None,
+        1 => Some(trace::operand(base, Position::Factor)),
+        _ => Some(format!("{}^{}", trace::operand(base, Position::Base), e)),
+    };
+    let term = |k: usize| {
+        let mut factors = vec![format!("C({}, {})", n, k)];
+        factors.extend(power(&high, n - k));
+        factors.extend(power(&low, k));
+        factors.join("*")
+    };
+    elide(n + 1, term, " + ")
+}
+
+/// The `count` parts joined by `separator`, with `⋯` for all but the first three
+/// and the last of a long run.
+fn elide(count: usize, part: impl Fn(usize) -> String, separator: &str) -> String {
+    let parts: Vec<String> = if count <= 5 {
+        (0..count).map(part).collect()
+    } else {
+        vec![part(0), part(1), part(2), "⋯".to_string(), part(count - 1)]
+    };
+    parts.join(separator)
+}
+
+// --- Budgeted Variants ---
+pub(crate) fn checked_factorial(n: usize, budget: &Budget) -> Result<BigInt, LimitExceeded> {
+    let n = n as u64;
+    budget.check(Limit::Factorial, n)?;
+    // n! < n^n
+    budget.check(Limit::CoeffBits, n.saturating_mul(bit_length(n)))?;
+    Ok(factorial(n as usize))
+}
+
+/// Checks the size of `pick(poly, k)`, which `choose` divides down from.
+pub(crate) fn check_pick<C: Coefficient>(
+    poly: &Polynomial<C>,
+    k: usize,
+    budget: &Budget,
+) -> Result<(), LimitExceeded> {
+    let k = k as u64;
+    // Each factor `poly - i` has at most one more term than `poly`.
+    let terms = poly.term_count() as u64 + 1;
+    let degree = (poly.degree() as u64).saturating_mul(k);
+    check_size(budget, degree, monomial_count(terms, k))?;
+    // ... and coefficients of at most this many bits.
+    let factor_bits = poly.max_bits().max(bit_length(k)) + 1;
+    let per_factor = factor_bits + bit_length(terms);
+    budget.check(Limit::CoeffBits, per_factor.saturating_mul(k))
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+
+    use super::{choose, factorial, pick, FactorialCache, FACTORIAL_CACHE_SIZE};
+    use crate::polynomial::{x, Polynomial};
+    use crate::ring::{Coefficient, Fp};
+
+    /// `poly * (poly - 1) * ... * (poly - k + 1)`, one factor at a time.
+    fn naive_pick<C: Coefficient>(poly: &Polynomial<C>, k: usize) -> Polynomial<C> {
+        let ctx = poly.ctx();
+        let one = Polynomial::constant(C::from_integer(1.into(), ctx), ctx);
+        (0..k).fold(one, |acc, i| {
+            acc * (poly.clone() - C::from_integer(i.into(), ctx))
+        })
+    }
+
+    #[test]
+    fn pick_and_choose_match_the_naive_product() {
+        let q = |n: i64, d: i64| BigRational::new(n.into(), d.into());
+        let x = || x(());
+        for poly in [
+            x(),
+            x() * &q(2, 1) + Polynomial::constant(q(1, 3), ()),
+            x() * x() - Polynomial::constant(q(1, 1), ()),
+            -x(),
+        ] {
+            for k in [0, 1, 2, 3, 17, 40] {
+                let expected = naive_pick(&poly, k);
+                assert_eq!(pick(&poly, k), expected, "P({}, {})", poly, k);
+                let k_factorial = BigRational::from_integer(factorial(k));
+                assert_eq!(choose(&poly, k), Some(expected / &k_factorial));
+            }
+        }
+    }
+
+    #[test]
+    fn pick_and_choose_mod_p_match_the_naive_product() {
+        let poly = x(7) * &Fp::from_integer(3.into(), 7) + Fp::from_integer(5.into(), 7);
+        for k in [0, 1, 2, 6, 7, 20] {
+            let expected = naive_pick(&poly, k);
+            assert_eq!(pick(&poly, k), expected);
+            let k_factorial = Fp::from_integer(factorial(k), 7);
+            assert_eq!(choose(&poly, k), expected.checked_div(&k_factorial));
+        }
+        // 7! is 0 mod 7, so C(poly, 7) doesn't exist.
+        assert_eq!(choose(&poly, 7), None);
+    }
+
+    #[test]
+    fn factorials_evict_the_least_recently_used() {
+        assert_eq!(factorial(0), BigInt::from(1));
+        assert_eq!(factorial(20), BigInt::from(2432902008176640000u64));
+        let product: BigInt = (1..=100u32).map(BigInt::from).product();
+        assert_eq!(factorial(100), product);
+
+        let mut cache = FactorialCache::default();
+        for n in 0..FACTORIAL_CACHE_SIZE {
+            cache.insert(n, factorial(n));
+        }
+        // Using 0! keeps it; 1! is now the stalest and goes first.
+        assert_eq!(cache.nearest_below(0), Some((0, BigInt::from(1))));
+        cache.insert(1000, factorial(1000));
+        assert_eq!(cache.entries.len(), FACTORIAL_CACHE_SIZE);
+        assert!(cache.entries.contains_key(&0));
+        assert!(!cache.entries.contains_key(&1));
+        assert_eq!(
+            cache.nearest_below(999).map(|(m, _)| m),
+            Some(FACTORIAL_CACHE_SIZE - 1)
+        );
+    }
 }
@@ -0,0 +1,129 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use miniz_oxide::deflate::compress_to_vec;
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+use crate::basis::Basis;
+
+const PREFIX: &str = "s=";
+const SEPARATOR: char = '\0';
+/// Guards against fragments that inflate to something enormous.
+const MAX_DECODED_LEN: usize = 1 << 20;
+
+// --- Session Permalinks ---
+/// What a permalink restores: the inputs, not the computed results.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Session {
+    pub(crate) expression: String,
+    pub(crate) basis: Basis,
+    pub(crate) eval_input: String,
+}
+
+impl Session {
+    /// Encodes the session as a URL fragment (without the leading `#`).
+    pub(crate) fn to_fragment(&self) -> String {
+        // The expression goes last since it's the only field that may contain anything.
+        let payload = format!(
+            "{}{sep}{}{sep}{}",
+            self.basis.name(),
+            self.eval_input,
+            self.expression,
+            sep = SEPARATOR
+        );
+        let compressed = compress_to_vec(payload.as_bytes(), 9);
+        format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode(compressed))
+    }
+
+    /// Decodes a fragment produced by `to_fragment`, with or without the leading `#`.
+    pub(crate) fn from_fragment(fragment: &str) -> Option<Session> {
+        let encoded = fragment.trim_start_matches('#').strip_prefix(PREFIX)?;
+        let compressed = URL_SAFE_NO_PAD.decode(encoded).ok()?;
+        let payload = decompress_to_vec_with_limit(&compressed, MAX_DECODED_LEN).ok()?;
+        let payload = String::from_utf8(payload).ok()?;
+
+        let mut fields = payload.splitn(3, SEPARATOR);
+        let basis = Basis::by_name(fields.next()?)?;
+        let eval_input = fields.next()?.to_string();
+        let expression = fields.next()?.to_string();
+        Some(Session {
+            expression,
+            basis,
+            eval_input,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use miniz_oxide::deflate::compress_to_vec;
+
+    use super::{Session, MAX_DECODED_LEN, PREFIX};
+    use crate::basis::Basis;
+
+    fn session(expression: &str, basis: Basis, eval_input: &str) -> Session {
+        Session {
+            expression: expression.to_string(),
+            basis,
+            eval_input: eval_input.to_string(),
+        }
+    }
+
+    fn fragment_of(payload: &[u8]) -> String {
+        let compressed = compress_to_vec(payload, 9);
+        format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode(compressed))
+    }
+
+    #[test]
+    fn sessions_round_trip() {
+        for session in [
+            session("C(x, 2) + 1/2", Basis::Binomial, "1, 2, 3"),
+            session("x² − ½·π", Basis::Standard, "∞, 日本"),
+            session("x^2", Basis::Standard, ""),
+            session("", Basis::Binomial, ""),
+            session("x\0 + 1\0\0", Basis::Standard, "0"),
+        ] {
+            let fragment = session.to_fragment();
+            assert!(fragment.is_ascii(), "{}", fragment);
+            assert_eq!(Session::from_fragment(&fragment).as_ref(), Some(&session));
+            let with_hash = format!("#{}", fragment);
+            assert_eq!(Session::from_fragment(&with_hash), Some(session));
+        }
+    }
+
+    #[test]
+    fn malformed_fragments_are_rejected() {
+        let fragment = session("x^2 + 1", Basis::Standard, "3").to_fragment();
+        let truncated = &fragment[..fragment.len() - 4];
+        let unprefixed = fragment.trim_start_matches(PREFIX);
+        for garbage in [
+            "",
+            "#",
+            "s=",
+            truncated,
+            unprefixed,
+            "s=not base64!",
+            "s=aGVsbG8",
+            &fragment_of(b"cubic\x002\x00x"),
+            &fragment_of(b"standard\x002"),
+            &fragment_of(b"standard\x00\xff\x00x"),
+        ] {
+            assert_eq!(Session::from_fragment(garbage), None, "{:?}", garbage);
+        }
+    }
+
+    #[test]
+    fn fragments_may_not_inflate_past_the_limit() {
+        let at_limit = format!("standard\0\0{}", "x".repeat(MAX_DECODED_LEN - 10));
+        assert_eq!(at_limit.len(), MAX_DECODED_LEN);
+        let session = Session::from_fragment(&fragment_of(at_limit.as_bytes())).unwrap();
+        assert_eq!(session.expression.len(), MAX_DECODED_LEN - 10);
+
+        let past_limit = format!("{}x", at_limit);
+        let fragment = fragment_of(past_limit.as_bytes());
+        // A deflated run of one letter is tiny, so only the limit stops it.
+        assert!(fragment.len() < 4096);
+        assert_eq!(Session::from_fragment(&fragment), None);
+    }
+}
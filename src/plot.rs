@@ -0,0 +1,547 @@
+use std::cell::Cell;
+use std::fmt::Write;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{Float, ToPrimitive, Zero};
+
+use crate::polynomial::Polynomial;
+
+const WIDTH: f64 = 500.0;
+const HEIGHT: f64 = 300.0;
+const INITIAL_SAMPLES: usize = 100;
+const MAX_SUBDIVISIONS: u32 = 8;
+/// Lattice points are only marked when this few integers are in view.
+const MAX_LATTICE_POINTS: usize = 200;
+/// Roughly how many word operations one plot may spend on exact values. Past
+/// this, samples near ill-conditioned roots keep their rounding error.
+const MAX_EXACT_WORK: u64 = 1 << 24;
+
+// --- Plot Viewport ---
+/// The visible x range. The y range is chosen automatically from the samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Viewport {
+    pub(crate) x_min: f64,
+    pub(crate) x_max: f64,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport {
+            x_min: -10.0,
+            x_max: 10.0,
+        }
+    }
+}
+
+impl Viewport {
+    /// Scales the width of the view by `factor` around its center.
+    pub(crate) fn zoom(self, factor: f64) -> Self {
+        let center = (self.x_min + self.x_max) / 2.0;
+        let half_width = (self.x_max - self.x_min) / 2.0 * factor;
+        Viewport {
+            x_min: center - half_width,
+            x_max: center + half_width,
+        }
+    }
+
+    /// Shifts the view by `fraction` of its width.
+    pub(crate) fn pan(self, fraction: f64) -> Self {
+        let shift = (self.x_max - self.x_min) * fraction;
+        Viewport {
+            x_min: self.x_min + shift,
+            x_max: self.x_max + shift,
+        }
+    }
+}
+
+// --- Sampling ---
+/// Evaluates a polynomial at floats. Dense polynomials are evaluated in floating
+/// point, with the coefficients scaled by `2^-scale` so that huge and tiny ones
+/// both fit, and exactly wherever rounding could swamp the value: near roots
+/// with large coefficients, such as `(x + 1)^100` around `-1`, as long as
+/// `MAX_EXACT_WORK` allows.
+struct Sampler<'a> {
+    poly: &'a Polynomial,
+    coeffs: Vec<f64>,
+    scale: i32,
+    /// What is left of `MAX_EXACT_WORK`.
+    exact_work: Cell<u64>,
+}
+
+impl<'a> Sampler<'a> {
+    fn new(poly: &'a Polynomial) -> Self {
+        if poly.is_sparse() {
+            return Sampler {
+                poly,
+                coeffs: Vec::new(),
+                scale: 0,
+                exact_work: Cell::new(0),
+            };
+        }
+        let numers = poly.numers();
+        let denom_bits = poly.denom().bits() as i64;
+        let exponents = numers
+            .iter()
+            .filter(|n| !n.is_zero())
+            .map(|n| n.bits() as i64 - denom_bits);
+        let (lo, hi) = exponents.fold((i64::MAX, i64::MIN), |(lo, hi), e| (lo.min(e), hi.max(e)));
+        let scale = if lo > hi { 0 } else { (lo + hi) / 2 };
+        let coeffs = numers
+            .iter()
+            .map(|n| dyadic_ratio(n.clone(), poly.denom().clone(), -scale))
+            .collect();
+        Sampler {
+            poly,
+            coeffs,
+            scale: scale as i32,
+            exact_work: Cell::new(MAX_EXACT_WORK),
+        }
+    }
+
+    fn eval(&self, x: f64) -> f64 {
+        // Exact powers of a float carry all its bits; for the high degree of a
+        // sparse polynomial that's far too many, and floating point does fine.
+        if self.poly.is_sparse() {
+            let terms = self.poly.terms().into_iter();
+            return terms
+                .map(|(k, c)| c.to_f64().unwrap_or(f64::NAN) * x.powf(k as f64))
+                .sum();
+        }
+        // Horner's rule, and alongside it the same for the absolute values, which
+        // bounds the rounding error.
+        let (mut value, mut bound) = (0.0, 0.0);
+        for &c in self.coeffs.iter().rev() {
+            value = value * x + c;
+            bound = bound * x.abs() + c.abs();
+        }
+        let error = bound * (2 * self.coeffs.len()) as f64 * f64::EPSILON;
+        if value.is_finite() && error > value.abs() * 1e-3 && self.spend_exact_work() {
+            return self.eval_exact(x);
+        }
+        ldexp(value, self.scale)
+    }
+
+    /// Whether an exact value still fits in `MAX_EXACT_WORK`, and if so deducts
+    /// its cost: a word operation per word of the sum, at most 64 bits of `x` per
+    /// degree on top of the largest numerator, for each coefficient.
+    fn spend_exact_work(&self) -> bool {
+        let numers = self.poly.numers();
+        let bits = numers.iter().map(|n| n.bits()).max().unwrap_or(0);
+        let words = bits / 64 + numers.len() as u64 + 1;
+        let cost = words.saturating_mul(numers.len() as u64);
+        let left = self.exact_work.get();
+        self.exact_work.set(left.saturating_sub(cost));
+        cost <= left
+    }
+
+    /// The value at `x` from an exact computation, rounded once at the end.
+    fn eval_exact(&self, x: f64) -> f64 {
+        let (numer, denom) = self.exact(x);
+        dyadic_ratio(numer, denom, 0)
+    }
+
+    /// The value at `x` of a dense polynomial, as an unreduced fraction.
+    fn exact(&self, x: f64) -> (BigInt, BigInt) {
+        // With `x = m / 2^f`, the value times `2^(f*degree)` is an integer sum,
+        // which Horner's rule builds up from integers of one word times `m`.
+        let numers = self.poly.numers();
+        if x == 0.0 {
+            let constant = numers.first().cloned().unwrap_or_default();
+            return (constant, self.poly.denom().clone());
+        }
+        let (mantissa, exponent, sign) = Float::integer_decode(x);
+        let zeros = mantissa.trailing_zeros();
+        let (mantissa, exponent) = (mantissa >> zeros, exponent + zeros as i16);
+        let m = BigInt::from(mantissa) * sign;
+        let (m, f) = if exponent >= 0 {
+            (m << exponent as usize, 0)
+        } else {
+            (m, (-exponent) as usize)
+        };
+        let degree = numers.len().saturating_sub(1);
+        let mut acc = BigInt::zero();
+        for (k, n) in numers.iter().enumerate().rev() {
+            acc = acc * &m + (n << (f * (degree - k)));
+        }
+        (acc, self.poly.denom() << (f * degree))
+    }
+}
+
+/// `numer / denom * 2^shift` as the nearest float, without reducing the fraction.
+fn dyadic_ratio(numer: BigInt, denom: BigInt, shift: i64) -> f64 {
+    if numer.is_zero() {
+        return 0.0;
+    }
+    // Keep 64 bits of the quotient and put the rest into the exponent.
+    let excess = numer.bits() as i64 - denom.bits() as i64 - 64;
+    let quotient = if excess > 0 {
+        numer / (denom << excess as usize)
+    } else {
+        (numer << (-excess) as usize) / denom
+    };
+    let shift = shift + excess;
+    ldexp(
+        quotient.to_f64().unwrap_or(f64::NAN),
+        shift.clamp(-4000, 4000) as i32,
+    )
+}
+
+/// `value * 2^exponent`, in two steps so that neither overflows on its own.
+fn ldexp(value: f64, exponent: i32) -> f64 {
+    let half = exponent / 2;
+    value * 2f64.powi(half) * 2f64.powi(exponent - half)
+}
+
+// --- Plotting ---
+struct Plotter<'a> {
+    sampler: Sampler<'a>,
+    view: Viewport,
+    y_min: f64,
+    y_max: f64,
+}
+
+impl Plotter<'_> {
+    fn eval(&self, x: f64) -> f64 {
+        self.sampler.eval(x)
+    }
+
+    fn to_screen(&self, x: f64, y: f64) -> (f64, f64) {
+        let sx = (x - self.view.x_min) / (self.view.x_max - self.view.x_min) * WIDTH;
+        let sy = (self.y_max - y) / (self.y_max - self.y_min) * HEIGHT;
+        (sx, sy)
+    }
+
+    /// Appends samples in `(x0, x1]`, splitting the interval wherever the curve
+    /// bends away from a straight line by more than half a pixel.
+    fn refine(
+        &self,
+        (x0, y0): (f64, f64),
+        (x1, y1): (f64, f64),
+        depth: u32,
+        out: &mut Vec<(f64, f64)>,
+    ) {
+        let xm = (x0 + x1) / 2.0;
+        let ym = self.eval(xm);
+        let tolerance = (self.y_max - self.y_min) / HEIGHT / 2.0;
+        if depth < MAX_SUBDIVISIONS && (ym - (y0 + y1) / 2.0).abs() > tolerance {
+            self.refine((x0, y0), (xm, ym), depth + 1, out);
+            self.refine((xm, ym), (x1, y1), depth + 1, out);
+        } else {
+            out.push((xm, ym));
+            out.push((x1, y1));
+        }
+    }
+
+    /// Locates real roots from sign changes between samples, refined by bisection.
+    fn roots(&self, samples: &[(f64, f64)]) -> Vec<f64> {
+        let mut roots: Vec<f64> = Vec::new();
+        for pair in samples.windows(2) {
+            let ((mut lo, y_lo), (mut hi, y_hi)) = (pair[0], pair[1]);
+            let root = if y_lo == 0.0 {
+                lo
+            } else if y_lo.signum() == y_hi.signum() {
+                continue;
+            } else {
+                for _ in 0..60 {
+                    let mid = (lo + hi) / 2.0;
+                    if self.eval(mid).signum() == y_lo.signum() {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                (lo + hi) / 2.0
+            };
+            if roots.last().is_none_or(|&last| root - last > 1e-9) {
+                roots.push(root);
+            }
+        }
+        roots
+    }
+
+    fn is_integer_at(&self, n: f64) -> bool {
+        let poly = self.sampler.poly;
+        if poly.is_sparse() {
+            let value = poly.eval(&BigRational::from_float(n).unwrap());
+            return value.is_integer();
+        }
+        let (numer, denom) = self.sampler.exact(n);
+        numer.is_multiple_of(&denom)
+    }
+
+    /// Integers in view at which the polynomial also takes an integer value.
+    fn lattice_points(&self) -> Vec<(f64, f64)> {
+        let (start, end) = (self.view.x_min.ceil(), self.view.x_max.floor());
+        if end - start >= MAX_LATTICE_POINTS as f64 {
+            return Vec::new();
+        }
+        let mut points = Vec::new();
+        let mut n = start;
+        while n <= end {
+            // The exact value can be enormous, but then it's nowhere near the view.
+            let y = self.eval(n);
+            if (self.y_min..=self.y_max).contains(&y) && self.is_integer_at(n) {
+                points.push((n, y));
+            }
+            n += 1.0;
+        }
+        points
+    }
+}
+
+/// Renders `poly` over `view` as a standalone SVG document.
+pub(crate) fn plot_svg(poly: &Polynomial, view: Viewport) -> String {
+    let mut plotter = Plotter {
+        sampler: Sampler::new(poly),
+        view,
+        y_min: 0.0,
+        y_max: 0.0,
+    };
+
+    let step = (view.x_max - view.x_min) / INITIAL_SAMPLES as f64;
+    let coarse: Vec<(f64, f64)> = (0..=INITIAL_SAMPLES)
+        .map(|i| {
+            let x = view.x_min + step * i as f64;
+            (x, plotter.eval(x))
+        })
+        .collect();
+    let finite = coarse.iter().map(|&(_, y)| y).filter(|y| y.is_finite());
+    let (y_min, y_max) = finite.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), y| {
+        (lo.min(y), hi.max(y))
+    });
+    let (y_min, y_max) = if !y_min.is_finite() || !y_max.is_finite() {
+        (-1.0, 1.0)
+    } else if y_max - y_min < 1e-12 {
+        (y_min - 1.0, y_max + 1.0)
+    } else {
+        let pad = (y_max - y_min) * 0.1;
+        (y_min - pad, y_max + pad)
+    };
+    plotter.y_min = y_min;
+    plotter.y_max = y_max;
+
+    let mut samples = vec![coarse[0]];
+    for pair in coarse.windows(2) {
+        plotter.refine(pair[0], pair[1], 0, &mut samples);
+    }
+
+    let mut svg = String::new();
+    write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {w} {h}" width="{w}" height="{h}">"#,
+        w = WIDTH,
+        h = HEIGHT
+    )
+    .unwrap();
+    write!(
+        svg,
+        r#"<rect width="{}" height="{}" fill="none" stroke="currentColor" stroke-opacity="0.3"/>"#,
+        WIDTH, HEIGHT
+    )
+    .unwrap();
+
+    // Axes
+    if view.x_min <= 0.0 && 0.0 <= view.x_max {
+        let (sx, _) = plotter.to_screen(0.0, 0.0);
+        write!(
+            svg,
+            r#"<line x1="{sx:.2}" y1="0" x2="{sx:.2}" y2="{HEIGHT}" stroke="currentColor" stroke-opacity="0.5"/>"#
+        )
+        .unwrap();
+    }
+    if y_min <= 0.0 && 0.0 <= y_max {
+        let (_, sy) = plotter.to_screen(0.0, 0.0);
+        write!(
+            svg,
+            r#"<line x1="0" y1="{sy:.2}" x2="{WIDTH}" y2="{sy:.2}" stroke="currentColor" stroke-opacity="0.5"/>"#
+        )
+        .unwrap();
+    }
+
+    // Curve
+    let path: Vec<String> = samples
+        .iter()
+        .filter(|(_, y)| y.is_finite())
+        .map(|&(x, y)| {
+            let (sx, sy) = plotter.to_screen(x, y);
+            format!("{:.2},{:.2}", sx, sy)
+        })
+        .collect();
+    write!(
+        svg,
+        r##"<polyline points="{}" fill="none" stroke="#63b3ed" stroke-width="2"/>"##,
+        path.join(" ")
+    )
+    .unwrap();
+
+    // Integer lattice points and real roots
+    for (x, y) in plotter.lattice_points() {
+        let (sx, sy) = plotter.to_screen(x, y);
+        write!(
+            svg,
+            r##"<circle cx="{sx:.2}" cy="{sy:.2}" r="2.5" fill="#48bb78"><title>({x}, {y})</title></circle>"##
+        )
+        .unwrap();
+    }
+    // Every point is a root of zero, so none is marked.
+    let roots = if poly.is_zero() {
+        Vec::new()
+    } else {
+        plotter.roots(&samples)
+    };
+    for root in roots {
+        let (sx, sy) = plotter.to_screen(root, 0.0);
+        write!(
+            svg,
+            r##"<circle cx="{sx:.2}" cy="{sy:.2}" r="4" fill="none" stroke="#f56565" stroke-width="2"><title>root ≈ {root:.6}</title></circle>"##
+        )
+        .unwrap();
+    }
+
+    // Range labels
+    write!(
+        svg,
+        r#"<g fill="currentColor" font-size="11" font-family="monospace"><text x="4" y="12">{:.4}</text><text x="4" y="{}">{:.4}</text><text x="4" y="{}" dominant-baseline="auto">x = {:.4}</text><text x="{}" y="{}" text-anchor="end">{:.4}</text></g>"#,
+        y_max,
+        HEIGHT - 16.0,
+        y_min,
+        HEIGHT - 4.0,
+        view.x_min,
+        WIDTH - 4.0,
+        HEIGHT - 4.0,
+        view.x_max
+    )
+    .unwrap();
+
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use num_rational::BigRational;
+
+    use super::{plot_svg, Sampler, Viewport};
+    use crate::budget::Budget;
+    use crate::parse::{parse, Associativity, Bindings, Parsed};
+    use crate::polynomial::Polynomial;
+
+    fn poly(query: &str) -> Polynomial {
+        let parsed = parse(
+            query,
+            &Bindings::default(),
+            &Budget::default(),
+            Associativity::Right,
+        );
+        match parsed.unwrap() {
+            Parsed::Rational(poly) => poly,
+            other => panic!("{} is not a rational polynomial: {}", query, other),
+        }
+    }
+
+    fn count(svg: &str, pattern: &str) -> usize {
+        svg.matches(pattern).count()
+    }
+
+    #[test]
+    fn constants_plot_as_level_lines() {
+        for query in ["0", "5/2", "-1000000"] {
+            let svg = plot_svg(&poly(query), Viewport::default());
+            assert!(svg.starts_with("<svg") && svg.ends_with("</svg>"));
+            let points = svg.split(r#"<polyline points=""#).nth(1).unwrap();
+            let points = points.split('"').next().unwrap();
+            // Centered, since a constant has no range of its own.
+            assert!(
+                points.split(' ').all(|p| p.ends_with(",150.00")),
+                "{}",
+                query
+            );
+            assert_eq!(count(&svg, "root ≈"), 0, "{}", query);
+        }
+        // Only the integer constants have lattice points.
+        assert_eq!(
+            count(&plot_svg(&poly("5/2"), Viewport::default()), "#48bb78"),
+            0
+        );
+        assert_eq!(
+            count(&plot_svg(&poly("0"), Viewport::default()), "#48bb78"),
+            21
+        );
+    }
+
+    #[test]
+    fn values_near_ill_conditioned_roots_are_exact() {
+        let power = poly("(x + 1)^100");
+        let sampler = Sampler::new(&power);
+        for x in [-1.001, -0.999, -1.0 - 1e-6] {
+            let expected = BigRational::from_float(x + 1.0).unwrap().pow(100);
+            let expected = num_traits::ToPrimitive::to_f64(&expected).unwrap();
+            let value = sampler.eval(x);
+            assert!(
+                (value - expected).abs() <= expected * 1e-9,
+                "{} at {}",
+                value,
+                x
+            );
+        }
+        assert_eq!(sampler.eval(-1.0), 0.0);
+
+        let svg = plot_svg(
+            &power,
+            Viewport {
+                x_min: -1.1,
+                x_max: -0.9,
+            },
+        );
+        assert!(!svg.contains("NaN") && !svg.contains("inf"));
+        // The root is found where the curve touches zero, though it doesn't cross.
+        assert_eq!(count(&svg, "root ≈"), 1);
+        assert!(svg.contains("root ≈ -1.000000"), "{}", svg);
+        assert_eq!(
+            count(
+                &plot_svg(&poly("(x + 1)^99"), Viewport::default()),
+                "root ≈"
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn exact_values_stop_at_the_work_limit() {
+        let poly = poly("(x + 1)^300");
+        let sampler = Sampler::new(&poly);
+        let mut x = -1.0005;
+        while sampler.exact_work.get() > 0 {
+            sampler.eval(x);
+            x += 1e-7;
+        }
+        // Past the limit, values keep their rounding error but stay finite.
+        assert!(sampler.eval(-1.0005).is_finite());
+        assert_eq!(sampler.exact_work.get(), 0);
+        plot_svg(
+            &poly,
+            Viewport {
+                x_min: -1.01,
+                x_max: -0.99,
+            },
+        );
+    }
+
+    #[test]
+    fn sparse_polynomials_plot_in_floating_point() {
+        let power = poly("x^1000000");
+        let sampler = Sampler::new(&power);
+        assert_eq!(sampler.eval(0.5), 0.0);
+        assert_eq!(sampler.eval(-1.0), 1.0);
+        assert_eq!(sampler.eval(1.5), f64::INFINITY);
+
+        let svg = plot_svg(&power, Viewport::default());
+        assert!(!svg.contains("NaN"));
+        // The lattice points in view are (-1, 1), (0, 0) and (1, 1).
+        assert_eq!(count(&svg, "#48bb78"), 3);
+        let svg = plot_svg(&poly("x^999999 - 1"), Viewport::default());
+        assert_eq!(count(&svg, "root ≈"), 1);
+    }
+}
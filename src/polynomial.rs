@@ -1,223 +1,700 @@
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::marker::PhantomData;
 use std::mem;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
+use num_bigint::BigInt;
+use num_integer::Integer;
 use num_rational::BigRational;
-use num_traits::{One, Zero};
+use num_traits::{One, Signed, Zero};
 
-use crate::format::format_from_coeffs;
+use crate::budget::{bit_length, Budget, Limit, LimitExceeded};
+use crate::format::{format_from_coeffs, CoeffStyle, Formatter, Plain};
+use crate::interrupt;
+use crate::multiply::{integer_product, Ring};
+use crate::pascal::binomial_power;
+use crate::ring::Coefficient;
+
+mod sparse;
 
 // --- Polynomial Struct and Operations ---
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
-pub(crate) struct Polynomial(Vec<BigRational>);
+/// Integer numerators over a denominator they all share, within the context its
+/// coefficients need, e.g. a modulus. There are no trailing zero numerators, so
+/// the zero polynomial has none, and `Coefficient::normalize` cancels common
+/// factors, so equal polynomials have equal representations. Every constructor
+/// and operator restores this through `from_parts` or `from_sparse`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Polynomial<C: Coefficient = BigRational> {
+    numers: Numers<BigInt>,
+    denom: BigInt,
+    ctx: C::Context,
+    coeffs: PhantomData<C>,
+}
 
-impl Polynomial {
-    pub(crate) fn constant(val: BigRational) -> Self {
-        Polynomial(vec![val])
+/// How a polynomial stores its numerators. Which one it uses depends only on its
+/// degree and number of terms; see `sparse::is_sparse`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Numers<N> {
+    /// Every numerator, constant term first.
+    Dense(Vec<N>),
+    /// The nonzero numerators, by exponent.
+    Sparse(BTreeMap<usize, N>),
+}
+
+impl<N: Ring> Numers<N> {
+    /// The nonzero numerators with their exponents, lowest first.
+    fn iter(&self) -> impl DoubleEndedIterator<Item = (usize, &N)> {
+        let (dense, sparse) = match self {
+            Numers::Dense(numers) => (Some(numers.iter().enumerate()), None),
+            Numers::Sparse(terms) => (None, Some(terms.iter().map(|(&k, n)| (k, n)))),
+        };
+        let dense = dense.into_iter().flatten().filter(|(_, n)| !n.is_zero());
+        dense.chain(sparse.into_iter().flatten())
+    }
+
+    fn values_mut(&mut self) -> impl Iterator<Item = &mut N> {
+        let (dense, sparse) = match self {
+            Numers::Dense(numers) => (Some(numers.iter_mut()), None),
+            Numers::Sparse(terms) => (None, Some(terms.values_mut())),
+        };
+        dense
+            .into_iter()
+            .flatten()
+            .chain(sparse.into_iter().flatten())
+    }
+
+    fn into_sparse(self) -> BTreeMap<usize, N> {
+        match self {
+            Numers::Dense(numers) => numers
+                .into_iter()
+                .enumerate()
+                .filter(|(_, n)| !n.is_zero())
+                .collect(),
+            Numers::Sparse(terms) => terms,
+        }
+    }
+}
+
+impl<C: Coefficient> Polynomial<C> {
+    pub(crate) fn zero(ctx: C::Context) -> Self {
+        Polynomial {
+            numers: Numers::Dense(Vec::new()),
+            denom: BigInt::one(),
+            ctx,
+            coeffs: PhantomData,
+        }
+    }
+
+    pub(crate) fn constant(val: C, ctx: C::Context) -> Self {
+        Polynomial::from_coeffs(vec![val], ctx)
+    }
+
+    /// The polynomial with these numerators, constant term first, over `denom`.
+    pub(crate) fn from_parts(mut numers: Vec<BigInt>, mut denom: BigInt, ctx: C::Context) -> Self {
+        C::normalize(&mut numers, &mut denom, ctx);
+        while numers.last().is_some_and(Zero::is_zero) {
+            numers.pop();
+        }
+        if numers.is_empty() {
+            return Polynomial::zero(ctx);
+        }
+        let terms = numers.iter().filter(|n| !n.is_zero()).count();
+        let numers = if sparse::is_sparse(terms as u64, numers.len() as u64 - 1) {
+            Numers::Sparse(Numers::Dense(numers).into_sparse())
+        } else {
+            Numers::Dense(numers)
+        };
+        Polynomial {
+            numers,
+            denom,
+            ctx,
+            coeffs: PhantomData,
+        }
+    }
+
+    /// The polynomial with numerators `terms`, keyed by exponent, over `denom`.
+    pub(crate) fn from_sparse(
+        terms: BTreeMap<usize, BigInt>,
+        mut denom: BigInt,
+        ctx: C::Context,
+    ) -> Self {
+        let (exponents, mut numers): (Vec<usize>, Vec<BigInt>) = terms.into_iter().unzip();
+        C::normalize(&mut numers, &mut denom, ctx);
+        let (exponents, numers): (Vec<usize>, Vec<BigInt>) = (exponents.into_iter().zip(numers))
+            .filter(|(_, n)| !n.is_zero())
+            .unzip();
+        let Some(&degree) = exponents.last() else {
+            return Polynomial::zero(ctx);
+        };
+        let numers = if sparse::is_sparse(numers.len() as u64, degree as u64) {
+            Numers::Sparse(exponents.into_iter().zip(numers).collect())
+        } else {
+            let mut dense = vec![BigInt::zero(); degree + 1];
+            for (k, n) in exponents.into_iter().zip(numers) {
+                dense[k] = n;
+            }
+            Numers::Dense(dense)
+        };
+        Polynomial {
+            numers,
+            denom,
+            ctx,
+            coeffs: PhantomData,
+        }
+    }
+
+    fn from_stored(numers: Numers<BigInt>, denom: BigInt, ctx: C::Context) -> Self {
+        match numers {
+            Numers::Dense(numers) => Polynomial::from_parts(numers, denom, ctx),
+            Numers::Sparse(terms) => Polynomial::from_sparse(terms, denom, ctx),
+        }
+    }
+
+    /// What the coefficients need besides their values, e.g. the modulus.
+    pub(crate) fn ctx(&self) -> C::Context {
+        self.ctx
     }
 
-    fn trim(&mut self) {
-        while self.degree() > 0 && self.0.last().unwrap().is_zero() {
-            self.0.pop();
+    pub(crate) fn eval(&self, x: &C) -> C {
+        let ctx = self.ctx;
+        let one = BigInt::one();
+        let mut result = C::from_integer(BigInt::zero(), ctx);
+        match &self.numers {
+            Numers::Dense(numers) => {
+                for n in numers.iter().rev() {
+                    result = result.mul(x, ctx).add(&C::from_fraction(n, &one, ctx), ctx);
+                }
+            }
+            // Horner's rule, with the gaps between exponents bridged by powers of `x`.
+            Numers::Sparse(terms) => {
+                let mut last = self.degree();
+                for (&k, n) in terms.iter().rev() {
+                    result = result.mul(&sparse::power(x, last - k, ctx), ctx);
+                    result = result.add(&C::from_fraction(n, &one, ctx), ctx);
+                    last = k;
+                }
+                result = result.mul(&sparse::power(x, last, ctx), ctx);
+            }
         }
+        result.mul(&C::from_fraction(&one, &self.denom, ctx), ctx)
     }
 
-    pub(crate) fn eval(&self, x: &BigRational) -> BigRational {
-        let mut result = BigRational::zero();
-        for c in self.0.iter().rev() {
-            result *= x;
-            result += c;
+    /// Substitutes `inner` for `x`.
+    pub(crate) fn compose(&self, inner: &Polynomial<C>) -> Polynomial<C> {
+        let ctx = self.ctx;
+        let one = BigInt::one();
+        let mut result = Polynomial::zero(ctx);
+        let mut last = self.degree();
+        for (k, n) in self.numers.iter().rev() {
+            result *= inner.clone().pow(last - k);
+            result += Polynomial::from_parts(vec![n.clone()], one.clone(), ctx);
+            last = k;
         }
-        result
+        result *= inner.clone().pow(last);
+        result * &C::from_fraction(&one, &self.denom, ctx)
     }
 
+    /// `self^n`: by the binomial theorem for two terms, by repeated squaring otherwise.
     pub(crate) fn pow(self, mut n: usize) -> Self {
+        if self.term_count() == 2 {
+            return binomial_power(&self, n);
+        }
+        let ctx = self.ctx;
         let mut base = self;
-        let mut acc = Polynomial::constant(BigRational::one());
-        while n >= 1 {
+        let mut acc = Polynomial::constant(C::from_integer(BigInt::one(), ctx), ctx);
+        while n >= 1 && !interrupt::checkpoint() {
             if n % 2 == 1 {
                 acc *= base.clone();
             }
-            base *= base.clone();
             n /= 2;
+            if n >= 1 {
+                base *= base.clone();
+            }
         }
         acc
     }
 
     pub(crate) fn is_zero(&self) -> bool {
-        self.coeffs().iter().all(|c| c.is_zero())
+        self.numers.iter().next().is_none()
     }
 
+    /// The degree, taking that of the zero polynomial to be 0.
     pub(crate) fn degree(&self) -> usize {
-        self.0.len().saturating_sub(1)
+        self.numers.iter().next_back().map_or(0, |(k, _)| k)
+    }
+
+    /// Whether only the nonzero terms are stored, as for `x^1000000 + 1`. Anything
+    /// that walks every coefficient up to the degree should avoid these.
+    pub(crate) fn is_sparse(&self) -> bool {
+        matches!(self.numers, Numers::Sparse(_))
+    }
+
+    /// The number of nonzero terms.
+    pub(crate) fn term_count(&self) -> usize {
+        self.numers.iter().count()
+    }
+
+    pub(crate) fn coeff_at(&self, n: usize) -> C {
+        let numer = match &self.numers {
+            Numers::Dense(numers) => numers.get(n),
+            Numers::Sparse(terms) => terms.get(&n),
+        };
+        let zero = BigInt::zero();
+        C::from_fraction(numer.unwrap_or(&zero), &self.denom, self.ctx)
+    }
+
+    /// The nonzero coefficients with their exponents, lowest first.
+    pub(crate) fn terms(&self) -> Vec<(usize, C)> {
+        self.numers
+            .iter()
+            .map(|(k, numer)| (k, C::from_fraction(numer, &self.denom, self.ctx)))
+            .collect()
+    }
+
+    /// All numerators over `denom()` up to the degree, constant term first.
+    pub(crate) fn numers(&self) -> Cow<'_, [BigInt]> {
+        match &self.numers {
+            Numers::Dense(numers) => Cow::Borrowed(numers),
+            Numers::Sparse(terms) => {
+                let mut numers = vec![BigInt::zero(); self.degree() + 1];
+                for (&k, n) in terms {
+                    numers[k] = n.clone();
+                }
+                Cow::Owned(numers)
+            }
+        }
+    }
+
+    pub(crate) fn denom(&self) -> &BigInt {
+        &self.denom
+    }
+
+    pub(crate) fn extract_constant(&self) -> Option<C> {
+        (self.degree() == 0).then(|| self.coeff_at(0))
+    }
+
+    /// Builds a polynomial from its coefficients, constant term first.
+    pub(crate) fn from_coeffs(coeffs: Vec<C>, ctx: C::Context) -> Self {
+        let (numers, denom) = C::over_common_denominator(&coeffs);
+        Polynomial::from_parts(numers, denom, ctx)
+    }
+
+    /// Builds a polynomial from coefficients with distinct exponents, in any order.
+    pub(crate) fn from_terms(terms: Vec<(usize, C)>, ctx: C::Context) -> Self {
+        let (exponents, coeffs): (Vec<usize>, Vec<C>) = terms.into_iter().unzip();
+        let (numers, denom) = C::over_common_denominator(&coeffs);
+        Polynomial::from_sparse(exponents.into_iter().zip(numers).collect(), denom, ctx)
+    }
+
+    /// Applies `f` to every nonzero coefficient, e.g. to move into another ring
+    /// with context `ctx`.
+    pub(crate) fn map<D: Coefficient>(
+        self,
+        mut f: impl FnMut(C) -> D,
+        ctx: D::Context,
+    ) -> Polynomial<D> {
+        let terms = self.terms().into_iter().map(|(k, c)| (k, f(c)));
+        Polynomial::from_terms(terms.collect(), ctx)
     }
 
-    pub(crate) fn coeff_at(&self, n: usize) -> BigRational {
-        self.0.get(n).cloned().unwrap_or_else(BigRational::zero)
+    /// Divides every coefficient by `rhs`, or returns `None` if `rhs` is zero.
+    pub(crate) fn checked_div(&self, rhs: &C) -> Option<Self> {
+        let one = C::from_integer(BigInt::one(), self.ctx);
+        let inverse = one.checked_div(rhs, self.ctx)?;
+        Some(self.clone() * &inverse)
     }
+}
+
+/// Scales every numerator by `factor`.
+fn scaled<N: Ring>(mut numers: Numers<N>, factor: &N) -> Numers<N> {
+    for n in numers.values_mut() {
+        *n *= factor;
+    }
+    numers
+}
 
-    pub(crate) fn coeffs(&self) -> &[BigRational] {
-        &self.0
+// --- Budgeted Operations ---
+/// These check a `Budget` against the size of the result before computing it. The
+/// bound on coefficient bits is an estimate: the bits of the factors, plus the
+/// carries from summing the products.
+impl<C: Coefficient> Polynomial<C> {
+    /// The size of the largest coefficient, as a numerator over the shared
+    /// denominator.
+    pub(crate) fn max_bits(&self) -> u64 {
+        let numer_bits = self.numers.iter().map(|(_, n)| n.bits()).max();
+        let numer_bits = numer_bits.unwrap_or(0);
+        // A denominator of 1 costs nothing.
+        numer_bits + self.denom.bits() - 1
     }
 
-    pub(crate) fn extract_constant(&self) -> Option<Cow<BigRational>> {
-        match self.0.len() {
-            0 => Some(Cow::Owned(BigRational::zero())),
-            1 => Some(Cow::Borrowed(&self.0[0])),
-            _ => None,
+    pub(crate) fn checked_mul(self, rhs: Self, budget: &Budget) -> Result<Self, LimitExceeded> {
+        if !self.is_zero() && !rhs.is_zero() {
+            let (lhs_terms, rhs_terms) = (self.term_count() as u64, rhs.term_count() as u64);
+            let degree = self.degree() as u64 + rhs.degree() as u64;
+            check_size(budget, degree, lhs_terms.saturating_mul(rhs_terms))?;
+            let terms = lhs_terms.min(rhs_terms);
+            let bits = self.max_bits() + rhs.max_bits() + bit_length(terms - 1);
+            budget.check(Limit::CoeffBits, bits)?;
         }
+        Ok(self * rhs)
+    }
+
+    pub(crate) fn checked_pow(self, n: usize, budget: &Budget) -> Result<Self, LimitExceeded> {
+        let steps = n as u64;
+        let terms = self.term_count() as u64;
+        let degree = (self.degree() as u64).saturating_mul(steps);
+        check_size(budget, degree, monomial_count(terms, steps))?;
+        // Powers of 0, x^k and -x^k don't grow their coefficients.
+        let unit = match &self.terms()[..] {
+            [] => true,
+            [(_, c)] => [1, -1]
+                .map(|u| C::from_integer(u.into(), self.ctx))
+                .contains(c),
+            _ => false,
+        };
+        if !unit {
+            let per_factor = self.max_bits() + bit_length(terms - 1);
+            budget.check(Limit::CoeffBits, per_factor.saturating_mul(steps))?;
+        }
+        Ok(self.pow(n))
     }
 }
 
-pub(crate) fn x() -> Polynomial {
-    Polynomial(vec![BigRational::zero(), BigRational::one()])
+/// Checks the size of a result with this degree and at most `terms` nonzero terms.
+/// One that's sure to be stored sparsely counts its terms instead, and its degree
+/// is bounded by `max_coeff_bits`, as its value at 2 has that many bits. That
+/// degree must also fit in a `usize`.
+pub(crate) fn check_size(budget: &Budget, degree: u64, terms: u64) -> Result<(), LimitExceeded> {
+    if !sparse::is_sparse(terms, degree) {
+        return budget.check(Limit::Degree, degree);
+    }
+    let allowed = budget.max_coeff_bits.min(usize::MAX as u64);
+    if degree > allowed {
+        return Err(LimitExceeded {
+            limit: Limit::Degree,
+            requested: degree,
+            allowed,
+        });
+    }
+    budget.check(Limit::Terms, terms)
+}
+
+/// How many terms a product of `n` polynomials with `terms` terms each can have:
+/// the number of ways to pick `n` of them with repetition, `C(n + terms - 1, n)`.
+pub(crate) fn monomial_count(terms: u64, n: u64) -> u64 {
+    let mut count: u128 = 1;
+    for i in 1..terms {
+        // `count` is `C(n + i - 1, i - 1)`, so this division is exact.
+        match count.checked_mul(u128::from(n) + u128::from(i)) {
+            Some(product) if product / u128::from(i) <= u128::from(u64::MAX) => {
+                count = product / u128::from(i);
+            }
+            _ => return u64::MAX,
+        }
+    }
+    count as u64
+}
+
+impl Polynomial {
+    /// Splits the polynomial into a rational content and a primitive part with
+    /// coprime integer coefficients and a positive leading coefficient.
+    pub(crate) fn content_and_primitive_part(&self) -> (BigRational, Polynomial) {
+        let Some((_, leading)) = self.numers.iter().next_back() else {
+            return (BigRational::zero(), Polynomial::zero(()));
+        };
+        let mut gcd = (self.numers.iter()).fold(BigInt::zero(), |gcd, (_, n)| gcd.gcd(n));
+        if leading.is_negative() {
+            gcd = -gcd;
+        }
+        let mut primitive = self.numers.clone();
+        for n in primitive.values_mut() {
+            *n /= &gcd;
+        }
+        let content = BigRational::new(gcd, self.denom.clone());
+        (
+            content,
+            Polynomial::from_stored(primitive, BigInt::one(), ()),
+        )
+    }
+}
+
+/// The content of a coefficient list is the gcd of the numerators over the lcm of
+/// the denominators, signed to match the leading coefficient. Dividing it out leaves
+/// coprime integers. The content of an all-zero list is zero.
+pub(crate) fn content_and_primitive_coeffs(
+    coeffs: &[BigRational],
+) -> (BigRational, Vec<BigRational>) {
+    let Some(leading) = coeffs.iter().rev().find(|c| !c.is_zero()) else {
+        return (BigRational::zero(), coeffs.to_vec());
+    };
+    let mut numer_gcd = BigInt::zero();
+    let mut denom_lcm = BigInt::one();
+    for c in coeffs.iter().filter(|c| !c.is_zero()) {
+        numer_gcd = numer_gcd.gcd(c.numer());
+        denom_lcm = denom_lcm.lcm(c.denom());
+    }
+    let mut content = BigRational::new(numer_gcd, denom_lcm);
+    if leading.is_negative() {
+        content = -content;
+    }
+    let primitive = coeffs.iter().map(|c| c / &content).collect();
+    (content, primitive)
+}
+
+pub(crate) fn x<C: Coefficient>(ctx: C::Context) -> Polynomial<C> {
+    Polynomial::from_parts(vec![BigInt::zero(), BigInt::one()], BigInt::one(), ctx)
 }
 
 // --- Operator Overloading ---
-impl Neg for Polynomial {
+impl<C: Coefficient> Neg for Polynomial<C> {
     type Output = Self;
-    fn neg(mut self) -> Self {
-        for coeff in &mut self.0 {
-            *coeff = -mem::take(coeff);
-        }
-        self
+    fn neg(self) -> Self {
+        let numers = scaled(self.numers, &-BigInt::one());
+        Polynomial::from_stored(numers, self.denom, self.ctx)
     }
 }
 
-impl Add for Polynomial {
+impl<C: Coefficient> Add for Polynomial<C> {
     type Output = Self;
     fn add(self, rhs: Self) -> Self {
-        let max_len = self.0.len().max(rhs.0.len());
-        let mut result_coeffs = vec![BigRational::zero(); max_len];
-        for (i, c) in self.0.iter().enumerate() {
-            result_coeffs[i] += c;
-        }
-        for (i, c) in rhs.0.iter().enumerate() {
-            result_coeffs[i] += c;
+        let ctx = self.ctx;
+        let (numers, other, denom) = if self.denom == rhs.denom {
+            (self.numers, rhs.numers, self.denom)
+        } else {
+            (
+                scaled(self.numers, &rhs.denom),
+                scaled(rhs.numers, &self.denom),
+                self.denom * rhs.denom,
+            )
+        };
+        match (numers, other) {
+            (Numers::Dense(mut numers), Numers::Dense(other)) => {
+                if numers.len() < other.len() {
+                    numers.resize(other.len(), BigInt::zero());
+                }
+                for (n, c) in numers.iter_mut().zip(&other) {
+                    *n += c;
+                }
+                Polynomial::from_parts(numers, denom, ctx)
+            }
+            (numers, other) => {
+                let terms = sparse::add(numers.into_sparse(), other.into_sparse());
+                Polynomial::from_sparse(terms, denom, ctx)
+            }
         }
-        let mut result = Polynomial(result_coeffs);
-        result.trim();
-        result
     }
 }
 
-impl AddAssign for Polynomial {
+impl<C: Coefficient> AddAssign for Polynomial<C> {
     fn add_assign(&mut self, rhs: Self) {
-        *self = mem::take(self) + rhs;
+        *self = self.take() + rhs;
     }
 }
 
-impl AddAssign<BigRational> for Polynomial {
-    fn add_assign(&mut self, rhs: BigRational) {
-        if self.0.is_empty() {
-            self.0.push(rhs);
-        } else {
-            self.0[0] += rhs;
-        }
-        self.trim();
+impl<C: Coefficient> AddAssign<C> for Polynomial<C> {
+    fn add_assign(&mut self, rhs: C) {
+        *self += Polynomial::constant(rhs, self.ctx);
     }
 }
 
-impl Add<BigRational> for Polynomial {
+impl<C: Coefficient> Add<C> for Polynomial<C> {
     type Output = Self;
-    fn add(mut self, rhs: BigRational) -> Self {
+    fn add(mut self, rhs: C) -> Self {
         self += rhs;
         self
     }
 }
 
-impl Sub for Polynomial {
+impl<C: Coefficient> Sub for Polynomial<C> {
     type Output = Self;
     fn sub(self, rhs: Self) -> Self {
         self + (-rhs)
     }
 }
 
-impl SubAssign for Polynomial {
+impl<C: Coefficient> SubAssign for Polynomial<C> {
     fn sub_assign(&mut self, rhs: Self) {
-        *self = mem::take(self) - rhs;
+        *self = self.take() - rhs;
     }
 }
 
-impl SubAssign<BigRational> for Polynomial {
-    fn sub_assign(&mut self, rhs: BigRational) {
-        if self.0.is_empty() {
-            self.0.push(-rhs);
-        } else {
-            self.0[0] -= rhs;
-        }
-        self.trim();
+impl<C: Coefficient> SubAssign<C> for Polynomial<C> {
+    fn sub_assign(&mut self, rhs: C) {
+        *self -= Polynomial::constant(rhs, self.ctx);
     }
 }
 
-impl Sub<BigRational> for Polynomial {
+impl<C: Coefficient> Sub<C> for Polynomial<C> {
     type Output = Self;
-    fn sub(mut self, rhs: BigRational) -> Self {
+    fn sub(mut self, rhs: C) -> Self {
         self -= rhs;
         self
     }
 }
 
-impl Mul for Polynomial {
+impl<C: Coefficient> Mul for Polynomial<C> {
     type Output = Self;
     fn mul(self, rhs: Self) -> Self {
+        let ctx = self.ctx;
         if self.is_zero() || rhs.is_zero() {
-            return Polynomial(vec![]);
+            return Polynomial::zero(ctx);
         }
-        let result_deg = self.degree() + rhs.degree();
-        let mut result_coeffs = vec![BigRational::zero(); result_deg + 1];
-        for (i, c1) in self.0.iter().enumerate() {
-            for (j, c2) in rhs.0.iter().enumerate() {
-                result_coeffs[i + j] += c1 * c2;
+        let denom = self.denom * rhs.denom;
+        match (&self.numers, &rhs.numers) {
+            (Numers::Dense(a), Numers::Dense(b)) => {
+                Polynomial::from_parts(integer_product(a, b), denom, ctx)
+            }
+            (a, b) => {
+                let (a, b): (Vec<_>, Vec<_>) = (a.iter().collect(), b.iter().collect());
+                Polynomial::from_sparse(sparse::mul(&a, &b), denom, ctx)
             }
         }
-        let mut result = Polynomial(result_coeffs);
-        result.trim();
-        result
     }
 }
 
-impl MulAssign for Polynomial {
+impl<C: Coefficient> MulAssign for Polynomial<C> {
     fn mul_assign(&mut self, rhs: Self) {
-        *self = self.clone() * rhs
+        *self = self.take() * rhs
     }
 }
 
-impl<'a> MulAssign<&'a BigRational> for Polynomial {
-    fn mul_assign(&mut self, rhs: &'a BigRational) {
-        for coeff in &mut self.0 {
-            *coeff *= rhs;
-        }
+impl<'a, C: Coefficient> MulAssign<&'a C> for Polynomial<C> {
+    fn mul_assign(&mut self, rhs: &'a C) {
+        let (numer, denom) = C::over_common_denominator(std::slice::from_ref(rhs));
+        let Polynomial {
+            numers,
+            denom: own,
+            ctx,
+            ..
+        } = self.take();
+        *self = Polynomial::from_stored(scaled(numers, &numer[0]), own * denom, ctx);
     }
 }
 
-impl<'a> Mul<&'a BigRational> for Polynomial {
+impl<'a, C: Coefficient> Mul<&'a C> for Polynomial<C> {
     type Output = Self;
-    fn mul(mut self, rhs: &'a BigRational) -> Self {
+    fn mul(mut self, rhs: &'a C) -> Self {
         self *= rhs;
         self
     }
 }
 
-impl<'a> DivAssign<&'a BigRational> for Polynomial {
-    fn div_assign(&mut self, rhs: &'a BigRational) {
-        for coeff in &mut self.0 {
-            *coeff /= rhs;
-        }
+impl<'a, C: Coefficient> DivAssign<&'a C> for Polynomial<C> {
+    fn div_assign(&mut self, rhs: &'a C) {
+        *self = self
+            .checked_div(rhs)
+            .expect("Division by a non-invertible constant");
     }
 }
 
-impl Div<&BigRational> for Polynomial {
+impl<C: Coefficient> Div<&C> for Polynomial<C> {
     type Output = Self;
-    fn div(mut self, rhs: &BigRational) -> Self {
+    fn div(mut self, rhs: &C) -> Self {
         self /= rhs;
         self
     }
 }
 
-impl fmt::Display for Polynomial {
+impl<C: Coefficient> Polynomial<C> {
+    /// Leaves zero in place of `self`, for the operators that assign.
+    fn take(&mut self) -> Self {
+        mem::replace(self, Polynomial::zero(self.ctx))
+    }
+}
+
+impl<C: Coefficient> fmt::Display for Polynomial<C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        format_from_coeffs(self.coeffs(), |degree| format!("x^{}", degree)).fmt(f)
+        let terms: Vec<(usize, BigRational)> =
+            (self.terms().iter()).map(|(k, c)| (*k, c.lift())).collect();
+        let terms = terms.iter().map(|(k, c)| (*k, c));
+        format_from_coeffs(terms, &Plain, CoeffStyle::Fraction, |degree| {
+            Plain.power(degree)
+        })
+        .fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+    use num_traits::{One, Zero};
+
+    use super::{x, Polynomial};
+
+    fn q(n: i64, d: i64) -> BigRational {
+        BigRational::new(n.into(), d.into())
+    }
+
+    fn ints(values: &[i64]) -> Vec<BigInt> {
+        values.iter().map(|&v| BigInt::from(v)).collect()
+    }
+
+    fn constant(c: BigRational) -> Polynomial {
+        Polynomial::constant(c, ())
+    }
+
+    #[test]
+    fn denominators_are_positive_and_coprime_to_the_numerators() {
+        let poly = Polynomial::<BigRational>::from_parts(ints(&[2, 4, 0, -6]), 6.into(), ());
+        assert_eq!(&poly.numers()[..], &ints(&[1, 2, 0, -3])[..]);
+        assert_eq!(poly.denom(), &BigInt::from(3));
+        let negative = Polynomial::<BigRational>::from_parts(ints(&[1, 2]), (-3).into(), ());
+        assert_eq!(&negative.numers()[..], &ints(&[-1, -2])[..]);
+        assert_eq!(negative.denom(), &BigInt::from(3));
+        // A common factor of only some numerators stays.
+        let partial = Polynomial::<BigRational>::from_parts(ints(&[4, 3]), 2.into(), ());
+        assert_eq!(partial.denom(), &BigInt::from(2));
+        assert_eq!(partial.coeff_at(0), q(2, 1));
+    }
+
+    #[test]
+    fn zero_has_one_representation() {
+        let zero = Polynomial::zero(());
+        assert!(zero.numers().is_empty());
+        assert!(zero.denom().is_one());
+        for poly in [
+            Polynomial::<BigRational>::from_parts(ints(&[0, 0, 0]), 5.into(), ()),
+            Polynomial::from_coeffs(vec![q(0, 1), q(0, 1)], ()),
+            Polynomial::from_terms(vec![(3, q(0, 1)), (70, q(0, 1))], ()),
+            constant(BigRational::zero()),
+            x(()) / &q(3, 1) - x(()) / &q(3, 1),
+            x(()).pow(100) - x(()).pow(100),
+            (x(()) + constant(q(1, 2))) * &BigRational::zero(),
+            -Polynomial::zero(()),
+            x(()) * Polynomial::zero(()),
+        ] {
+            assert_eq!(poly, zero);
+            assert!(poly.denom().is_one());
+            assert!(poly.numers().is_empty());
+            assert_eq!(poly.degree(), 0);
+            assert_eq!(poly.term_count(), 0);
+        }
+    }
+
+    #[test]
+    fn equal_polynomials_built_differently_compare_equal() {
+        let x = || x(());
+        let expanded = Polynomial::from_coeffs(vec![q(1, 1), q(2, 1), q(1, 1)], ());
+        assert_eq!((x() + constant(q(1, 1))).pow(2), expanded);
+        assert_eq!(x() * x() + x() * &q(2, 1) + constant(q(1, 1)), expanded);
+        assert_eq!(x() / &q(2, 1) + x() / &q(2, 1), x());
+        assert_eq!(x() * &q(1, 6) * &q(3, 1), x() * &q(1, 2));
+        assert_eq!(
+            (x() + constant(q(2, 1))) * &q(1, 2),
+            Polynomial::from_terms(vec![(1, q(1, 2)), (0, q(1, 1))], ())
+        );
+        assert_eq!(x() * &q(2, 3) - x() * &q(1, 6), x() / &q(2, 1));
+        assert_eq!(
+            Polynomial::<BigRational>::from_parts(ints(&[3, 6]), 9.into(), ()),
+            Polynomial::<BigRational>::from_parts(ints(&[-1, -2]), (-3).into(), ())
+        );
+        assert_eq!(expanded.compose(&(x() - constant(q(1, 1)))), x().pow(2));
+        assert_eq!(
+            (x() + constant(q(1, 3))).compose(&(x() - constant(q(1, 1)))),
+            x() - constant(q(2, 3))
+        );
     }
 }
@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+
+use num_bigint::BigInt;
+use num_traits::One;
+
+use crate::interrupt;
+use crate::multiply::Ring;
+use crate::ring::Coefficient;
+
+// --- Sparse Numerators ---
+// A polynomial with few terms for its degree keeps only its nonzero numerators,
+// by exponent, so that `x^1000000 + 1` takes two entries instead of a million.
+
+/// Below this degree, storing every numerator is always cheap enough.
+const SPARSE_MIN_DEGREE: u64 = 64;
+/// Sparse storage pays off once at most one numerator in this many is nonzero.
+const SPARSE_RATIO: u64 = 8;
+
+/// Whether a polynomial with `terms` nonzero terms and this degree is stored
+/// sparsely. Depending only on the polynomial keeps its representation unique.
+pub(super) fn is_sparse(terms: u64, degree: u64) -> bool {
+    degree >= SPARSE_MIN_DEGREE && terms.saturating_mul(SPARSE_RATIO) <= degree.saturating_add(1)
+}
+
+pub(super) fn add<N: Ring>(mut a: BTreeMap<usize, N>, b: BTreeMap<usize, N>) -> BTreeMap<usize, N> {
+    for (k, c) in b {
+        *a.entry(k).or_insert_with(N::zero) += &c;
+    }
+    a
+}
+
+/// Multiplies term by term, which takes time in the number of terms rather than
+/// in the degree.
+pub(super) fn mul<N: Ring>(a: &[(usize, &N)], b: &[(usize, &N)]) -> BTreeMap<usize, N> {
+    let mut result = BTreeMap::new();
+    for &(i, c1) in a {
+        if interrupt::checkpoint() {
+            break;
+        }
+        for &(j, c2) in b {
+            let mut product = c1.clone();
+            product *= c2;
+            *result.entry(i + j).or_insert_with(N::zero) += &product;
+        }
+    }
+    result
+}
+
+/// `base^n` by repeated squaring.
+pub(super) fn power<C: Coefficient>(base: &C, mut n: usize, ctx: C::Context) -> C {
+    let mut base = base.clone();
+    let mut acc = C::from_integer(BigInt::one(), ctx);
+    while n > 0 {
+        if n % 2 == 1 {
+            acc = acc.mul(&base, ctx);
+        }
+        n /= 2;
+        if n > 0 {
+            base = base.mul(&base, ctx);
+        }
+    }
+    acc
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+    use num_traits::{One, Zero};
+
+    use super::is_sparse;
+    use crate::basis::Basis;
+    use crate::format::{CoeffStyle, Plain};
+    use crate::polynomial::{x, Polynomial};
+
+    fn q(n: i64) -> BigRational {
+        BigRational::from_integer(n.into())
+    }
+
+    fn one() -> Polynomial {
+        Polynomial::constant(q(1), ())
+    }
+
+    fn x_to(n: usize) -> Polynomial {
+        x(()).pow(n)
+    }
+
+    #[test]
+    fn representation_switches_at_the_threshold() {
+        assert!(!is_sparse(2, 63));
+        assert!(is_sparse(2, 64));
+        assert!(is_sparse(8, 64));
+        assert!(!is_sparse(9, 64));
+        assert!(is_sparse(1, u64::MAX));
+        assert!(!(x_to(63) + one()).is_sparse());
+        assert!((x_to(64) + one()).is_sparse());
+
+        // Just below the ratio: eight terms up to x^64 are sparse, nine are not.
+        let low: Polynomial = (1..7).map(x_to).fold(one(), |acc, t| acc + t);
+        let eight = low.clone() + x_to(64);
+        let nine = eight.clone() + x_to(7);
+        assert!(eight.is_sparse());
+        assert!(!nine.is_sparse());
+        let mut coeffs = vec![q(0); 65];
+        for k in (0..8).chain([64]) {
+            coeffs[k] = q(1);
+        }
+        assert_eq!(Polynomial::from_coeffs(coeffs, ()), nine);
+        assert_eq!(nine - x_to(7), eight);
+    }
+
+    #[test]
+    fn sparse_and_dense_operands_give_equal_results() {
+        let dense: Polynomial = (0..8)
+            .map(x_to)
+            .fold(Polynomial::zero(()), |acc, t| acc + t);
+        let sparse = x_to(64);
+        let mixed = sparse.clone() + dense.clone();
+        assert!(!mixed.is_sparse());
+        assert_eq!(mixed.clone() - dense.clone(), sparse);
+        assert_eq!(mixed - sparse.clone(), dense);
+
+        // Dense factors with a sparse product, and sparse factors with a dense one.
+        let product = (x_to(32) + one()) * (x_to(32) - one());
+        assert!(product.is_sparse());
+        assert_eq!(
+            product,
+            Polynomial::from_terms(vec![(64, q(1)), (0, q(-1))], ())
+        );
+        assert_eq!(product.clone() + one(), sparse);
+        assert_eq!((x_to(64) + x(())) - x_to(64), x(()));
+        assert_eq!(sparse.clone() * (x(()) + one()), x_to(65) + x_to(64));
+        assert_eq!(
+            (x_to(64) + one()) * &BigRational::new(1.into(), 2.into()),
+            product / &q(2) + one()
+        );
+        assert_eq!(
+            (x_to(64) + one()).pow(2),
+            (x_to(64) + one()) * (x_to(64) + one())
+        );
+    }
+
+    #[test]
+    fn huge_sparse_polynomials_stay_cheap() {
+        let poly = x_to(1_000_000) + one();
+        assert!(poly.is_sparse());
+        assert_eq!(poly.term_count(), 2);
+
+        let product = poly.clone() * (x_to(1_000_000) - one());
+        assert_eq!(product, x_to(2_000_000) - one());
+        assert_eq!(product.term_count(), 2);
+
+        assert_eq!(poly.eval(&q(0)), q(1));
+        assert_eq!(poly.eval(&q(1)), q(2));
+        assert_eq!(poly.eval(&q(-1)), q(2));
+        let big = BigRational::from_integer((BigInt::one() << 1_000_000usize) + 1);
+        assert_eq!(poly.eval(&q(2)), big);
+
+        assert_eq!(poly.to_string(), "x^1000000 + 1");
+        assert_eq!(
+            Basis::Binomial.format(&poly, &Plain, CoeffStyle::Fraction, false),
+            "x^1000000 + 1"
+        );
+        assert_eq!(
+            Basis::Binomial.format(&(poly.clone() * &q(2)), &Plain, CoeffStyle::Fraction, true),
+            "2*(x^1000000 + 1)"
+        );
+
+        assert_eq!(poly.extract_constant(), None);
+        assert_eq!(
+            (poly.clone() - x_to(1_000_000)).extract_constant(),
+            Some(q(1))
+        );
+        assert!((poly.clone() - poly).extract_constant().unwrap().is_zero());
+    }
+}
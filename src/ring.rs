@@ -0,0 +1,275 @@
+use std::fmt;
+use std::mem;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+// --- Coefficient Rings ---
+/// The operations `Polynomial` needs from its coefficients. Whatever they need
+/// besides their values, such as a modulus, is passed in as a `Context`, which
+/// every polynomial carries, so that each coefficient has one representation.
+pub(crate) trait Coefficient: Clone + fmt::Debug + fmt::Display + PartialEq + Eq {
+    /// Whatever is needed besides the values themselves, e.g. a modulus.
+    type Context: Copy + fmt::Debug + PartialEq + Eq;
+
+    /// The integer `n` within `ctx`, e.g. reduced modulo a prime.
+    fn from_integer(n: BigInt, ctx: Self::Context) -> Self;
+
+    fn add(&self, rhs: &Self, ctx: Self::Context) -> Self;
+
+    fn mul(&self, rhs: &Self, ctx: Self::Context) -> Self;
+
+    /// `self / rhs`, or `None` if `rhs` is zero.
+    fn checked_div(&self, rhs: &Self, ctx: Self::Context) -> Option<Self>;
+
+    /// The least positive integer that is zero within `ctx`, or 0 if there is none.
+    fn characteristic(ctx: Self::Context) -> u64;
+
+    /// The rational number this coefficient stands for. Elements of GF(p) lift to
+    /// their representative in `0..p`.
+    fn lift(&self) -> BigRational;
+
+    // A polynomial stores integer numerators over one shared denominator, so that
+    // its arithmetic needn't normalize a fraction per coefficient.
+
+    /// Numerators over a common denominator for `coeffs`.
+    fn over_common_denominator(coeffs: &[Self]) -> (Vec<BigInt>, BigInt);
+
+    /// The coefficient `numer / denom`, where `denom` comes from a polynomial.
+    fn from_fraction(numer: &BigInt, denom: &BigInt, ctx: Self::Context) -> Self;
+
+    /// Cancels whatever `denom` shares with all of `numers`, so that equal
+    /// polynomials are stored the same way.
+    fn normalize(numers: &mut [BigInt], denom: &mut BigInt, ctx: Self::Context);
+
+    /// Shrinks a numerator that a loop keeps multiplying, without changing the
+    /// polynomial it ends up in once normalized.
+    fn reduce(_numer: &mut BigInt, _ctx: Self::Context) {}
+}
+
+impl Coefficient for BigRational {
+    type Context = ();
+
+    fn from_integer(n: BigInt, (): ()) -> Self {
+        BigRational::from_integer(n)
+    }
+
+    fn add(&self, rhs: &Self, (): ()) -> Self {
+        self + rhs
+    }
+
+    fn mul(&self, rhs: &Self, (): ()) -> Self {
+        self * rhs
+    }
+
+    fn checked_div(&self, rhs: &Self, (): ()) -> Option<Self> {
+        if rhs.is_zero() {
+            None
+        } else {
+            Some(self / rhs)
+        }
+    }
+
+    fn characteristic((): ()) -> u64 {
+        0
+    }
+
+    fn lift(&self) -> BigRational {
+        self.clone()
+    }
+
+    /// Scales the numerators to the lcm of the denominators.
+    fn over_common_denominator(coeffs: &[Self]) -> (Vec<BigInt>, BigInt) {
+        let denom = coeffs
+            .iter()
+            .filter(|c| !c.denom().is_one())
+            .fold(BigInt::one(), |lcm, c| lcm.lcm(c.denom()));
+        let numers = coeffs
+            .iter()
+            .map(|c| {
+                if c.denom() == &denom {
+                    c.numer().clone()
+                } else {
+                    c.numer() * (&denom / c.denom())
+                }
+            })
+            .collect();
+        (numers, denom)
+    }
+
+    fn from_fraction(numer: &BigInt, denom: &BigInt, (): ()) -> Self {
+        if denom.is_one() {
+            BigRational::from_integer(numer.clone())
+        } else {
+            BigRational::new(numer.clone(), denom.clone())
+        }
+    }
+
+    /// Leaves a positive denominator coprime to the gcd of the numerators.
+    fn normalize(numers: &mut [BigInt], denom: &mut BigInt, (): ()) {
+        if denom.is_negative() {
+            *denom = -mem::take(denom);
+            for n in numers.iter_mut() {
+                *n = -mem::take(n);
+            }
+        }
+        let mut gcd = denom.clone();
+        for n in numers.iter() {
+            if gcd.is_one() {
+                return;
+            }
+            gcd = gcd.gcd(n);
+        }
+        if !gcd.is_one() {
+            *denom /= &gcd;
+            for n in numers.iter_mut() {
+                *n /= &gcd;
+            }
+        }
+    }
+}
+
+// --- Prime Field ---
+/// An element of GF(p), as its representative in `0..p`. The modulus is the
+/// context: polynomials over GF(p) carry it, and evaluation passes it along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Fp(u64);
+
+impl Fp {
+    /// Converts `p/q` into GF(`modulus`), or `None` if `q` is divisible by `modulus`.
+    pub(crate) fn from_rational(r: &BigRational, modulus: u64) -> Option<Self> {
+        let numer = Fp::from_integer(r.numer().clone(), modulus);
+        numer.checked_div(&Fp::from_integer(r.denom().clone(), modulus), modulus)
+    }
+
+    fn residue(n: &BigInt, modulus: u64) -> u64 {
+        n.mod_floor(&BigInt::from(modulus)).to_u64().unwrap()
+    }
+}
+
+/// Moduli are below 2^32 (see `check_prime_modulus`), so products of two
+/// representatives fit in a `u64`.
+impl Coefficient for Fp {
+    type Context = u64;
+
+    fn from_integer(n: BigInt, modulus: u64) -> Self {
+        Fp(Fp::residue(&n, modulus))
+    }
+
+    fn add(&self, rhs: &Self, modulus: u64) -> Self {
+        Fp((self.0 + rhs.0) % modulus)
+    }
+
+    fn mul(&self, rhs: &Self, modulus: u64) -> Self {
+        Fp(self.0 * rhs.0 % modulus)
+    }
+
+    fn checked_div(&self, rhs: &Self, modulus: u64) -> Option<Self> {
+        if rhs.0 == 0 {
+            return None;
+        }
+        // Fermat's little theorem: a^(p-2) is the inverse of a mod p.
+        let p = BigInt::from(modulus);
+        let inverse = BigInt::from(rhs.0).modpow(&(&p - 2u32), &p);
+        Some(self.mul(&Fp::from_integer(inverse, modulus), modulus))
+    }
+
+    fn characteristic(modulus: u64) -> u64 {
+        modulus
+    }
+
+    fn lift(&self) -> BigRational {
+        BigRational::from_integer(self.0.into())
+    }
+
+    fn over_common_denominator(coeffs: &[Self]) -> (Vec<BigInt>, BigInt) {
+        (coeffs.iter().map(|c| c.0.into()).collect(), BigInt::one())
+    }
+
+    fn from_fraction(numer: &BigInt, denom: &BigInt, modulus: u64) -> Self {
+        Fp::from_integer(numer.clone(), modulus)
+            .checked_div(&Fp::from_integer(denom.clone(), modulus), modulus)
+            .expect("Denominators mod p are invertible")
+    }
+
+    /// Folds the denominator into the numerators, so that it's always 1, and
+    /// leaves each numerator in `0..p`.
+    fn normalize(numers: &mut [BigInt], denom: &mut BigInt, modulus: u64) {
+        let scale = Fp::from_fraction(&BigInt::one(), denom, modulus);
+        for n in numers.iter_mut() {
+            *n = Fp::from_integer(mem::take(n), modulus)
+                .mul(&scale, modulus)
+                .0
+                .into();
+        }
+        *denom = BigInt::one();
+    }
+
+    fn reduce(numer: &mut BigInt, modulus: u64) {
+        *numer = Fp::residue(numer, modulus).into();
+    }
+}
+
+impl fmt::Display for Fp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Checks that `p` is a prime small enough for trial division to be instant.
+pub(crate) fn check_prime_modulus(p: u64) -> Result<(), String> {
+    if p >= 1 << 32 {
+        return Err(format!("Modulus {} is too large", p));
+    }
+    let is_prime = p >= 2
+        && (2..)
+            .take_while(|d| d * d <= p)
+            .all(|d| !p.is_multiple_of(d));
+    if is_prime {
+        Ok(())
+    } else {
+        Err(format!("Modulus must be prime, got {}", p))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+
+    use super::{Coefficient, Fp};
+    use crate::polynomial::{x, Polynomial};
+
+    fn fp(n: i64) -> Fp {
+        Fp::from_integer(n.into(), 7)
+    }
+
+    #[test]
+    fn elements_of_gf_p_have_one_representation() {
+        assert_eq!(fp(7), fp(0));
+        assert_eq!(fp(-1), fp(6));
+        assert_eq!(fp(3).add(&fp(4), 7), fp(0));
+        assert_eq!(fp(3).mul(&fp(5), 7), fp(1));
+        assert_eq!(fp(1).checked_div(&fp(3), 7), Some(fp(5)));
+        assert_eq!(fp(1).checked_div(&fp(14), 7), None);
+        let half = BigRational::new(BigInt::from(1), BigInt::from(2));
+        assert_eq!(Fp::from_rational(&half, 7), Some(fp(4)));
+        assert_eq!(fp(-8).lift(), BigRational::from_integer(6.into()));
+    }
+
+    #[test]
+    fn polynomials_mod_p_reduce_to_one_representation() {
+        let zero = Polynomial::<Fp>::zero(7);
+        let seven_x = x(7) * &fp(7);
+        assert!(seven_x.is_zero());
+        assert_eq!(seven_x, zero);
+        assert_eq!(Polynomial::from_coeffs(vec![fp(7), fp(14)], 7), zero);
+        let sum = x(7) + Polynomial::constant(fp(6), 7) * x(7);
+        assert_eq!(sum, zero);
+        let shifted = (x(7) + Polynomial::constant(fp(1), 7)).pow(7);
+        assert_eq!(shifted, x(7).pow(7) + Polynomial::constant(fp(1), 7));
+        assert_eq!(shifted.eval(&fp(6)), fp(0));
+    }
+}
@@ -0,0 +1,279 @@
+use std::cell::RefCell;
+
+use num_traits::{One, Signed};
+
+use crate::polynomial::Polynomial;
+use crate::ring::Coefficient;
+
+// --- Evaluation Traces ---
+// While `record` runs a computation, the evaluator opens a step for each
+// reduction it makes and closes it at the value. The helpers the reduction calls,
+// such as `pascal::choose`, add the forms they go through and the rules they
+// apply. Outside `record` all of this does nothing, and no form is formatted.
+
+/// Steps beyond this many are left out, so that a long sum doesn't bury the rest.
+const MAX_STEPS: usize = 100;
+/// Forms are cut off after this many characters.
+const MAX_FORM_CHARS: usize = 200;
+
+/// One reduction, as a chain of equal forms: the subexpression with its operands
+/// already reduced, any intermediate forms, and the value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Step {
+    pub(crate) forms: Vec<String>,
+    /// The named rule that leads from the first form to the rest, if any.
+    pub(crate) rule: Option<String>,
+}
+
+/// The steps of a computation, in the order they were taken.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Trace {
+    pub(crate) steps: Vec<Step>,
+    /// Whether steps past `MAX_STEPS` were left out.
+    pub(crate) truncated: bool,
+}
+
+#[derive(Default)]
+struct Recorder {
+    trace: Trace,
+    /// Whether the last step is still being computed.
+    open: bool,
+}
+
+thread_local! {
+    static RECORDER: RefCell<Option<Recorder>> = const { RefCell::new(None) };
+}
+
+/// Runs `compute`, and also returns the steps it took if `enabled`.
+pub(crate) fn record<T>(enabled: bool, compute: impl FnOnce() -> T) -> (T, Trace) {
+    if !enabled {
+        return (compute(), Trace::default());
+    }
+    RECORDER.with(|r| *r.borrow_mut() = Some(Recorder::default()));
+    let result = compute();
+    let mut recorder = RECORDER.with(|r| r.borrow_mut().take()).unwrap_or_default();
+    if recorder.open {
+        // The reduction failed
+        recorder.trace.steps.pop();
+    }
+    (result, recorder.trace)
+}
+
+/// Opens a step at `subject`, the reduction about to be computed. One left open
+/// by a reduction that failed is dropped.
+pub(crate) fn step(subject: impl FnOnce() -> String) {
+    RECORDER.with(|r| {
+        let mut r = r.borrow_mut();
+        let Some(recorder) = r.as_mut() else {
+            return;
+        };
+        if recorder.open {
+            recorder.trace.steps.pop();
+        }
+        recorder.open = recorder.trace.steps.len() < MAX_STEPS;
+        if !recorder.open {
+            recorder.trace.truncated = true;
+            return;
+        }
+        recorder.trace.steps.push(Step {
+            forms: vec![clip(subject())],
+            rule: None,
+        });
+    });
+}
+
+/// Adds an intermediate form to the open step.
+pub(crate) fn form(form: impl FnOnce() -> String) {
+    with_open_step(|step| step.forms.push(clip(form())));
+}
+
+/// Names the rule the open step applies.
+pub(crate) fn rule(name: &str) {
+    with_open_step(|step| step.rule = Some(name.to_string()));
+}
+
+/// Closes the open step at `value`. A step that didn't change how anything is
+/// written, such as `x + 1`, is dropped.
+pub(crate) fn conclude<C: Coefficient>(value: &Polynomial<C>) {
+    RECORDER.with(|r| {
+        let mut r = r.borrow_mut();
+        let Some(recorder) = r.as_mut().filter(|recorder| recorder.open) else {
+            return;
+        };
+        recorder.open = false;
+        let step = recorder.trace.steps.last_mut().unwrap();
+        step.forms.push(clip(value.to_string()));
+        step.forms.dedup();
+        if step.forms.len() == 1 {
+            recorder.trace.steps.pop();
+        }
+    });
+}
+
+fn with_open_step(update: impl FnOnce(&mut Step)) {
+    RECORDER.with(|r| {
+        if let Some(recorder) = r.borrow_mut().as_mut().filter(|recorder| recorder.open) {
+            update(recorder.trace.steps.last_mut().unwrap());
+        }
+    });
+}
+
+fn clip(mut form: String) -> String {
+    if let Some((end, _)) = form.char_indices().nth(MAX_FORM_CHARS) {
+        form.truncate(end);
+        form.push('…');
+    }
+    form
+}
+
+/// Where a polynomial appears in a form, which decides whether it needs parentheses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Position {
+    /// Added or subtracted: bare if it's a single term that isn't negative.
+    Summand,
+    /// Multiplied or divided, or negated: bare if it's a single term with a
+    /// nonnegative integer coefficient.
+    Factor,
+    /// Raised to a power: bare if it's `x` or a nonnegative integer.
+    Base,
+}
+
+/// `poly` as an operand at `position` in a form.
+pub(crate) fn operand<C: Coefficient>(poly: &Polynomial<C>, position: Position) -> String {
+    let bare = match &poly.terms()[..] {
+        [] => true,
+        [(k, c)] => {
+            let c = c.lift();
+            !c.is_negative()
+                && match position {
+                    Position::Summand => true,
+                    Position::Factor => c.is_integer(),
+                    Position::Base => c.is_integer() && (*k == 0 || (*k == 1 && c.is_one())),
+                }
+        }
+        _ => false,
+    };
+    if bare {
+        poly.to_string()
+    } else {
+        format!("({})", poly)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use num_rational::BigRational;
+
+    use super::{conclude, form, record, rule, step, Step, Trace, MAX_STEPS};
+    use crate::budget::Budget;
+    use crate::parse::{parse, Associativity, Bindings};
+    use crate::polynomial::{x, Polynomial};
+
+    fn trace_of(query: &str) -> Trace {
+        let parse = || {
+            parse(
+                query,
+                &Bindings::default(),
+                &Budget::default(),
+                Associativity::Right,
+            )
+        };
+        record(true, parse).1
+    }
+
+    fn step_of(rule: Option<&str>, forms: &[&str]) -> Step {
+        Step {
+            forms: forms.iter().map(|f| f.to_string()).collect(),
+            rule: rule.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn reductions_record_their_forms_and_rules() {
+        let trace = trace_of("C(x, 2)");
+        let expected = step_of(
+            Some("binomial coefficient"),
+            &["C(x, 2)", "x*(x - 1)/2", "(1/2)*x^2 - (1/2)*x"],
+        );
+        assert_eq!(trace.steps, vec![expected]);
+        assert!(!trace.truncated);
+
+        let trace = trace_of("(x + 1)^3");
+        let expected = step_of(
+            Some("binomial theorem"),
+            &[
+                "(x + 1)^3",
+                "C(3, 0)*x^3 + C(3, 1)*x^2 + C(3, 2)*x + C(3, 3)",
+                "x^3 + 3*x^2 + 3*x + 1",
+            ],
+        );
+        assert_eq!(trace.steps, vec![expected]);
+    }
+
+    #[test]
+    fn failed_reductions_are_dropped() {
+        let trace = trace_of("C(x, 2) + 1/0");
+        assert_eq!(trace.steps.len(), 1);
+        assert_eq!(trace.steps[0].forms[0], "C(x, 2)");
+
+        let ((), trace) = record(true, || {
+            step(|| "abandoned".to_string());
+            step(|| "kept".to_string());
+            conclude(&x::<BigRational>(()));
+            step(|| "failed".to_string());
+        });
+        assert_eq!(trace.steps, vec![step_of(None, &["kept", "x"])]);
+    }
+
+    #[test]
+    fn steps_past_the_limit_are_left_out() {
+        let ((), trace) = record(true, || {
+            for i in 0..MAX_STEPS + 5 {
+                step(|| format!("step {}", i));
+                conclude(&x::<BigRational>(()));
+            }
+        });
+        assert_eq!(trace.steps.len(), MAX_STEPS);
+        assert_eq!(
+            trace.steps.last().unwrap().forms[0],
+            format!("step {}", MAX_STEPS - 1)
+        );
+        assert!(trace.truncated);
+
+        let sum = vec!["C(x, 2)"; MAX_STEPS].join(" + ");
+        assert!(trace_of(&sum).truncated);
+    }
+
+    #[test]
+    fn nothing_is_formatted_unless_enabled() {
+        let formatted = Cell::new(0);
+        let count = || {
+            formatted.set(formatted.get() + 1);
+            String::new()
+        };
+        let ((), trace) = record(false, || {
+            step(count);
+            form(count);
+            rule("rule");
+            conclude(&Polynomial::<BigRational>::zero(()));
+        });
+        assert_eq!(formatted.get(), 0);
+        assert_eq!(trace, Trace::default());
+        // Nor outside `record`.
+        step(count);
+        form(count);
+        assert_eq!(formatted.get(), 0);
+        assert_eq!(trace_of("C(x, 2)").steps.len(), 1);
+        let (_, trace) = record(false, || {
+            parse(
+                "C(x, 2)",
+                &Bindings::default(),
+                &Budget::default(),
+                Associativity::Right,
+            )
+        });
+        assert!(trace.steps.is_empty());
+    }
+}
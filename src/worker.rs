@@ -0,0 +1,430 @@
+use std::cell::Cell;
+use std::str::FromStr;
+
+use js_sys::{Atomics, Int32Array, Object, Reflect, SharedArrayBuffer};
+use num_rational::BigRational;
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{HtmlLinkElement, MessageEvent, Worker, WorkerOptions, WorkerType};
+
+use crate::basis::Integrality;
+use crate::budget::Budget;
+use crate::history::{escape, unescape};
+use crate::interrupt;
+use crate::parse::{parse, Associativity, Bindings, Identity, Parsed, Witness};
+use crate::polynomial::Polynomial;
+use crate::ring::{check_prime_modulus, Coefficient, Fp};
+use crate::trace::{self, Step, Trace};
+
+// --- Worker Protocol ---
+// The page and the worker exchange plain strings. A request is the id, the
+// budget, the associativity of `^`, `1` to trace the calculation or `0`, the
+// number of previous results, one line per result and then the query, which may
+// span lines. A response is the id, then `ok <value>` or `err <message>`, then
+// the binomial-basis coefficients that the page cites as the value's integrality
+// or `-`, then one line per step of the trace. Messages and traced forms are
+// escaped as in the history (see `history::escape`), so each takes one line.
+
+/// Identifies a request. Ids are `i32`, the element type of the shared cancel flag,
+/// so the page and the worker compare them the same way.
+pub(crate) type RequestId = i32;
+
+/// A calculation sent to the worker.
+pub(crate) struct Request<'a> {
+    pub(crate) id: RequestId,
+    pub(crate) query: &'a str,
+    pub(crate) budget: Budget,
+    pub(crate) powers: Associativity,
+    /// Whether to record the steps of the calculation.
+    pub(crate) trace: bool,
+    /// Previous results, most recent first, as for `Bindings`.
+    pub(crate) answers: Vec<Option<&'a Parsed>>,
+}
+
+impl Request<'_> {
+    pub(crate) fn encode(&self) -> String {
+        let mut lines = vec![
+            self.id.to_string(),
+            self.budget.to_string(),
+            self.powers.name().to_string(),
+            u8::from(self.trace).to_string(),
+            self.answers.len().to_string(),
+        ];
+        lines.extend(
+            self.answers
+                .iter()
+                .map(|a| a.map_or_else(|| "-".to_string(), encode_value)),
+        );
+        lines.push(self.query.to_string());
+        lines.join("\n")
+    }
+}
+
+/// Runs an encoded request and encodes the response.
+fn handle(request: &str) -> Option<String> {
+    let mut lines = request.splitn(6, '\n');
+    let id: RequestId = lines.next()?.parse().ok()?;
+    CURRENT_REQUEST.set(id);
+    let budget: Budget = lines.next()?.parse().ok()?;
+    let powers = Associativity::by_name(lines.next()?)?;
+    let tracing = lines.next()? == "1";
+    let count: usize = lines.next()?.parse().ok()?;
+    let mut rest = lines.next().unwrap_or("");
+    let mut answers = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (line, tail) = rest.split_once('\n')?;
+        answers.push(decode_value(line));
+        rest = tail;
+    }
+    let bindings = Bindings::new(answers.iter().map(Option::as_ref).collect());
+    let (outcome, trace) = trace::record(tracing, || parse(rest, &bindings, &budget, powers));
+    let integrality = outcome.as_ref().ok().and_then(Integrality::of);
+    let mut lines = vec![
+        id.to_string(),
+        match outcome {
+            Ok(value) => format!("ok {}", encode_value(&value)),
+            Err(e) => format!("err {}", escape(&e)),
+        },
+        integrality.map_or_else(
+            || "-".to_string(),
+            |i| {
+                i.certificate
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            },
+        ),
+    ];
+    lines.extend(encode_trace(&trace));
+    Some(lines.join("\n"))
+}
+
+/// What the worker answers to a request.
+pub(crate) struct Response {
+    /// The id of the request.
+    pub(crate) id: RequestId,
+    pub(crate) outcome: Result<Parsed, String>,
+    /// See `Integrality::of`.
+    pub(crate) integrality: Option<Integrality>,
+    pub(crate) trace: Trace,
+}
+
+pub(crate) fn decode_response(response: &str) -> Option<Response> {
+    let mut lines = response.split('\n');
+    let id = lines.next()?.parse().ok()?;
+    let outcome = match lines.next()?.split_once(' ')? {
+        ("ok", value) => Ok(decode_value(value)?),
+        ("err", message) => Err(unescape(message)),
+        _ => return None,
+    };
+    let integrality = match lines.next()? {
+        "-" => None,
+        certificate => Some(Integrality::new(
+            certificate
+                .split(' ')
+                .map(BigRational::from_str)
+                .collect::<Result<_, _>>()
+                .ok()?,
+        )),
+    };
+    Some(Response {
+        id,
+        outcome,
+        integrality,
+        trace: decode_trace(lines)?,
+    })
+}
+
+/// `q` or `p<modulus>`, then the nonzero terms as `exponent:coefficient`. The
+/// outcome of a comparison is `t`, or `f` followed by the witness if there is one.
+pub(crate) fn encode_value(value: &Parsed) -> String {
+    let (tag, fields) = match value {
+        Parsed::Identity(Identity { holds: true, .. }) => ("t".to_string(), vec![]),
+        Parsed::Identity(Identity { witness, .. }) => (
+            "f".to_string(),
+            witness
+                .iter()
+                .flat_map(|w| [w.x.to_string(), w.lhs.to_string(), w.rhs.to_string()])
+                .collect(),
+        ),
+        Parsed::Rational(poly) => ("q".to_string(), encode_terms(poly.terms())),
+        Parsed::Modular { poly, modulus } => (
+            format!("p{}", modulus),
+            encode_terms(
+                poly.terms()
+                    .into_iter()
+                    .map(|(k, c)| (k, c.lift()))
+                    .collect(),
+            ),
+        ),
+    };
+    let mut line = vec![tag];
+    line.extend(fields);
+    line.join(" ")
+}
+
+fn encode_terms(terms: Vec<(usize, BigRational)>) -> Vec<String> {
+    terms.iter().map(|(k, c)| format!("{}:{}", k, c)).collect()
+}
+
+fn decode_terms<'a>(fields: impl Iterator<Item = &'a str>) -> Option<Vec<(usize, BigRational)>> {
+    fields
+        .map(|field| {
+            let (k, c) = field.split_once(':')?;
+            Some((k.parse().ok()?, c.parse().ok()?))
+        })
+        .collect()
+}
+
+pub(crate) fn decode_value(s: &str) -> Option<Parsed> {
+    let mut fields = s.split(' ');
+    let tag = fields.next()?;
+    if tag == "q" {
+        return Some(Parsed::Rational(Polynomial::from_terms(
+            decode_terms(fields)?,
+            (),
+        )));
+    }
+    if let Some(modulus) = tag.strip_prefix('p') {
+        let modulus: u64 = modulus.parse().ok()?;
+        check_prime_modulus(modulus).ok()?;
+        let terms = decode_terms(fields)?
+            .into_iter()
+            .map(|(k, c)| {
+                c.is_integer()
+                    .then(|| (k, Fp::from_integer(c.to_integer(), modulus)))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let poly = Polynomial::from_terms(terms, modulus);
+        return Some(Parsed::Modular { poly, modulus });
+    }
+    let coeffs = fields
+        .map(BigRational::from_str)
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    match (tag, coeffs.as_slice()) {
+        ("t", []) | ("f", []) => Some(Parsed::Identity(Identity {
+            holds: tag == "t",
+            witness: None,
+        })),
+        ("f", [x, lhs, rhs]) => {
+            let witness = Witness {
+                x: x.clone(),
+                lhs: lhs.clone(),
+                rhs: rhs.clone(),
+            };
+            Some(Parsed::Identity(Identity {
+                holds: false,
+                witness: Some(witness),
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// One line per step: its rule, which may be empty, and its forms, escaped and
+/// separated by tabs. A trace that left steps out ends with a line `...`.
+fn encode_trace(trace: &Trace) -> Vec<String> {
+    let mut lines: Vec<String> = trace
+        .steps
+        .iter()
+        .map(|step| {
+            let mut fields = vec![escape(step.rule.as_deref().unwrap_or(""))];
+            fields.extend(step.forms.iter().map(|form| escape(form)));
+            fields.join("\t")
+        })
+        .collect();
+    if trace.truncated {
+        lines.push("...".to_string());
+    }
+    lines
+}
+
+fn decode_trace<'a>(lines: impl Iterator<Item = &'a str>) -> Option<Trace> {
+    let mut trace = Trace::default();
+    for line in lines {
+        if line == "..." {
+            trace.truncated = true;
+            continue;
+        }
+        let mut fields = line.split('\t').map(unescape);
+        let rule = fields.next()?;
+        trace.steps.push(Step {
+            forms: fields.collect(),
+            rule: (!rule.is_empty()).then_some(rule),
+        });
+    }
+    Some(trace)
+}
+
+// --- Worker Entry Points ---
+thread_local! {
+    static CURRENT_REQUEST: Cell<RequestId> = const { Cell::new(0) };
+}
+
+/// Whether the current request is one of those cancelled by storing `flag`.
+fn is_cancelled(flag: RequestId) -> bool {
+    flag >= CURRENT_REQUEST.get()
+}
+
+/// Called once by `worker.js`. When the page can share memory with the worker,
+/// it cancels requests by storing their id in `cancel_flag`, and every request
+/// up to that id stops at its next checkpoint.
+#[wasm_bindgen]
+pub fn worker_init(cancel_flag: Option<Int32Array>) {
+    if let Some(flag) = cancel_flag {
+        interrupt::set_check(move || Atomics::load(&flag, 0).is_ok_and(is_cancelled));
+    }
+}
+
+/// Called by `worker.js` for every request the page posts.
+#[wasm_bindgen]
+pub fn worker_compute(request: &str) -> String {
+    handle(request).unwrap_or_else(|| format!("0\nerr Malformed request: {:?}\n-", request))
+}
+
+// --- Page Side ---
+/// The page's handle on a worker running this same module.
+pub(crate) struct CalcWorker {
+    worker: Worker,
+    init_message: Object,
+    cancel_flag: Option<Int32Array>,
+    on_message: Closure<dyn FnMut(MessageEvent)>,
+}
+
+impl CalcWorker {
+    /// Starts `worker.js`, or returns `None` if the browser or the page's
+    /// bundle doesn't allow it. `on_response` gets every response.
+    pub(crate) fn spawn(mut on_response: impl FnMut(Response) + 'static) -> Option<Self> {
+        let document = web_sys::window()?.document()?;
+        let link_href = |selector: &str| {
+            let link = document.query_selector(selector).ok()??;
+            Some(link.dyn_into::<HtmlLinkElement>().ok()?.href())
+        };
+        let glue = link_href(r#"link[rel="modulepreload"][href$=".js"]"#)?;
+        let wasm = link_href(r#"link[href$="_bg.wasm"]"#)?;
+
+        // Cancelling through shared memory needs a cross-origin isolated page;
+        // otherwise a busy worker can only be terminated.
+        let isolated = Reflect::get(&js_sys::global(), &"crossOriginIsolated".into())
+            .is_ok_and(|v| v.is_truthy());
+        let cancel_flag = isolated.then(|| Int32Array::new(&SharedArrayBuffer::new(4)));
+
+        let init_message = Object::new();
+        Reflect::set(&init_message, &"type".into(), &"init".into()).ok()?;
+        Reflect::set(&init_message, &"glue".into(), &glue.into()).ok()?;
+        Reflect::set(&init_message, &"wasm".into(), &wasm.into()).ok()?;
+        let flag_value = cancel_flag.as_ref().map_or(JsValue::NULL, |f| f.into());
+        Reflect::set(&init_message, &"cancelFlag".into(), &flag_value).ok()?;
+
+        let on_message = Closure::<dyn FnMut(_)>::new(move |event: MessageEvent| {
+            if let Some(response) = event
+                .data()
+                .as_string()
+                .as_deref()
+                .and_then(decode_response)
+            {
+                on_response(response);
+            }
+        });
+        let worker = start_worker(&init_message, &on_message)?;
+        Some(CalcWorker {
+            worker,
+            init_message,
+            cancel_flag,
+            on_message,
+        })
+    }
+
+    pub(crate) fn post(&self, request: &Request) {
+        self.worker.post_message(&request.encode().into()).unwrap();
+    }
+
+    /// Stops request `id`. Without shared memory that means replacing the worker.
+    pub(crate) fn cancel(&mut self, id: RequestId) {
+        if let Some(flag) = &self.cancel_flag {
+            Atomics::store(flag, 0, id).unwrap();
+            return;
+        }
+        self.worker.terminate();
+        if let Some(worker) = start_worker(&self.init_message, &self.on_message) {
+            self.worker = worker;
+        }
+    }
+}
+
+fn start_worker(
+    init_message: &Object,
+    on_message: &Closure<dyn FnMut(MessageEvent)>,
+) -> Option<Worker> {
+    let options = WorkerOptions::new();
+    options.set_type(WorkerType::Module);
+    let worker = Worker::new_with_options("worker.js", &options).ok()?;
+    worker.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    worker.post_message(init_message).ok()?;
+    Some(worker)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::{decode_response, handle, is_cancelled, Request, RequestId, Response};
+    use crate::budget::Budget;
+    use crate::interrupt;
+    use crate::parse::Associativity;
+
+    fn run(id: RequestId, query: &str) -> Response {
+        let request = Request {
+            id,
+            query,
+            budget: Budget::default(),
+            powers: Associativity::Right,
+            trace: false,
+            answers: vec![],
+        };
+        decode_response(&handle(&request.encode()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn multi_line_errors_stay_in_their_field() {
+        let request = Request {
+            id: 3,
+            query: "x + 1\n\tx\\2\n...",
+            budget: Budget::default(),
+            powers: Associativity::Right,
+            trace: true,
+            answers: vec![],
+        };
+        let response = decode_response(&handle(&request.encode()).unwrap()).unwrap();
+        assert_eq!(response.id, 3);
+        assert_eq!(
+            response.outcome.unwrap_err(),
+            "Unexpected input after parsing: '\\2\n...'"
+        );
+        assert!(response.trace.steps.is_empty() && !response.trace.truncated);
+    }
+
+    #[test]
+    fn cancelled_requests_stop_and_later_ones_run() {
+        // Stands in for the shared flag the page stores cancelled ids in.
+        let flag = Rc::new(Cell::new(0));
+        let shared = Rc::clone(&flag);
+        interrupt::set_check(move || is_cancelled(shared.get()));
+        let query = "C(x, 40)";
+        for id in [7, RequestId::MAX - 1] {
+            flag.set(id);
+            let cancelled = run(id, query);
+            assert_eq!(cancelled.id, id);
+            assert_eq!(cancelled.outcome.unwrap_err(), "Cancelled");
+            assert!(cancelled.integrality.is_none());
+            // The next request starts afresh, despite the earlier interruption.
+            let next = run(id + 1, query);
+            assert_eq!(next.id, id + 1);
+            assert!(next.outcome.is_ok());
+            assert!(next.integrality.is_some());
+        }
+        interrupt::set_check(|| false);
+    }
+}
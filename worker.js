@@ -0,0 +1,17 @@
+// Runs calculations off the page's main thread. The page first sends the URLs of
+// the wasm-bindgen glue and module, then one request string per calculation;
+// see src/worker.rs.
+let calculator;
+
+onmessage = async (event) => {
+    if (event.data.type === "init") {
+        calculator = import(event.data.glue).then(async (glue) => {
+            await glue.default({ module_or_path: event.data.wasm });
+            glue.worker_init(event.data.cancelFlag);
+            return glue;
+        });
+        return;
+    }
+    const glue = await calculator;
+    postMessage(glue.worker_compute(event.data));
+};